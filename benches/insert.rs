@@ -0,0 +1,85 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use radix_trie::{Layout, RadixTrie, TrieBuilder};
+
+/// every key of two bytes, so each node on the first level
+/// grows to a full set of 256 children.
+fn dense_keys() -> Vec<Vec<u8>> {
+    (0..=u16::MAX)
+        .map(|key| key.to_be_bytes().to_vec())
+        .collect()
+}
+
+/// pseudo-random keys of 8 bytes, which branch widely near the
+/// root and thin out into single chains below it.
+fn random_keys(count: usize) -> Vec<Vec<u8>> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state.to_be_bytes().to_vec()
+        })
+        .collect()
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for (name, keys) in [("dense", dense_keys()), ("random", random_keys(100_000))] {
+        group.throughput(Throughput::Elements(keys.len() as u64));
+        for layout in [Layout::Adaptive, Layout::Bitmap] {
+            group.bench_function(format!("{name}/{layout:?}"), |b| {
+                b.iter_batched(
+                    || keys.clone(),
+                    |keys| {
+                        let mut trie = RadixTrie::<_>::with_layout(layout);
+                        for (value, key) in keys.into_iter().enumerate() {
+                            trie.insert(key, value);
+                        }
+                        black_box(trie)
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+/// loading sorted keys one insert at a time, against a TrieBuilder.
+fn bench_bulk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk");
+    let mut keys = random_keys(100_000);
+    keys.sort();
+    group.throughput(Throughput::Elements(keys.len() as u64));
+    group.bench_function("insert", |b| {
+        b.iter_batched(
+            || keys.clone(),
+            |keys| {
+                let mut trie = RadixTrie::new();
+                for (value, key) in keys.into_iter().enumerate() {
+                    trie.insert(key, value);
+                }
+                black_box(trie)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("builder", |b| {
+        b.iter_batched(
+            || keys.clone(),
+            |keys| {
+                let mut builder = TrieBuilder::new();
+                for (value, key) in keys.into_iter().enumerate() {
+                    builder.push(key, value);
+                }
+                black_box(builder.build())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert, bench_bulk);
+criterion_main!(benches);
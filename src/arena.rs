@@ -0,0 +1,444 @@
+use std::marker::PhantomData;
+use std::num::NonZeroU32;
+use std::ops::{Deref, Index, IndexMut};
+use std::sync::Arc;
+
+use crate::children::Layout;
+use crate::node::RadixNode;
+
+/// The position of a node in the arena of its trie. Ids are one
+/// more than the index of their slot, so an Option<NodeId> takes
+/// no more room than the id itself, and None is all zero bits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(transparent)]
+pub(crate) struct NodeId(NonZeroU32);
+
+/// the id of the root, which is allocated with the arena and never freed.
+pub(crate) const ROOT: NodeId = NodeId(NonZeroU32::MIN);
+
+impl NodeId {
+    fn new(index: usize) -> Self {
+        let id = u32::try_from(index + 1)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .expect("a RadixTrie holds fewer than 2^32 nodes");
+        NodeId(id)
+    }
+
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Owns every node of a trie, so nodes refer to their children by id
+/// rather than through pointers of their own. Slots of freed nodes
+/// are chained into a free list and reused before the arena grows.
+/// As no node owns another, dropping the arena drops the nodes one
+/// after another, however deep the trie is.
+///
+/// Once the arena is shared with a snapshot, its slots are held in
+/// chunks of CHUNK, each copied the first time it is written to while
+/// still shared, so later snapshots cost one reference per chunk and
+/// writes only copy the chunks they touch. Until then the slots are
+/// kept in one vector, and a trie never snapshotted pays nothing.
+#[derive(Clone)]
+pub(crate) struct Arena<T> {
+    slots: Slots<T>,
+    /// the number of slots, whether in use or free.
+    len: usize,
+    /// the most recently freed slot, if any.
+    free: Option<NodeId>,
+    /// the number of nodes in use, including the root.
+    count: usize,
+    /// how the children of every node are stored.
+    pub(crate) layout: Layout,
+}
+
+#[derive(Clone)]
+enum Slots<T> {
+    /// the slots of an arena which has not been shared.
+    Flat(Vec<Slot<T>>),
+    Chunked {
+        chunks: Vec<Chunk<T>>,
+        /// copies the slots of a shared chunk. Set when the arena
+        /// is first shared, as only then must T be Clone.
+        copy: CopySlots<T>,
+    },
+}
+
+type CopySlots<T> = fn(&[Slot<T>]) -> Vec<Slot<T>>;
+
+/// the number of slots in a chunk.
+const CHUNK: usize = 64;
+
+#[derive(Clone)]
+enum Chunk<T> {
+    Owned(Vec<Slot<T>>),
+    /// a chunk shared with snapshots, which is copied before it is
+    /// written to unless the snapshots have all been dropped.
+    Shared(Arc<Vec<Slot<T>>>),
+}
+
+#[derive(Clone)]
+enum Slot<T> {
+    Occupied(RadixNode<T>),
+    /// a free slot, linking to the slot freed before it.
+    Free(Option<NodeId>),
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new(layout: Layout) -> Self {
+        let mut arena = Self {
+            slots: Slots::Flat(Vec::new()),
+            len: 0,
+            free: None,
+            count: 1,
+            layout,
+        };
+        arena.push(Slot::Occupied(RadixNode::new()));
+        arena
+    }
+
+    /// drops every node but the root, which is left empty.
+    /// The vector keeps its capacity for later insertions. Slots held
+    /// in chunks are left to any snapshots sharing them instead, and
+    /// the arena starts over with one vector.
+    pub(crate) fn clear(&mut self) {
+        match &mut self.slots {
+            Slots::Flat(slots) => {
+                slots.truncate(1);
+                slots[ROOT.index()] = Slot::Occupied(RadixNode::new());
+                self.len = 1;
+            }
+            Slots::Chunked { .. } => {
+                self.slots = Slots::Flat(Vec::new());
+                self.len = 0;
+                self.push(Slot::Occupied(RadixNode::new()));
+            }
+        }
+        self.free = None;
+        self.count = 1;
+    }
+
+    /// moves the nodes into slots with no free ones among them and no
+    /// spare capacity, and shrinks the labels and children of each.
+    /// The nodes are renumbered breadth first from the root, into one
+    /// vector of slots shared with no snapshot.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let empty = Self {
+            slots: Slots::Flat(Vec::with_capacity(self.count)),
+            len: 0,
+            free: None,
+            count: self.count,
+            layout: self.layout,
+        };
+        let mut old = std::mem::replace(self, empty);
+        self.push(old.take(ROOT));
+
+        // The children of each node moved so far are moved in
+        // after the nodes already there, and take their new ids.
+        let mut moved = Vec::new();
+        let mut next = 0;
+        while next < self.len {
+            let end = self.len;
+            let Slot::Occupied(node) = self.slot_mut(next) else {
+                unreachable!("only nodes in use are moved")
+            };
+            node.label.shrink_to_fit();
+            if let Some(children) = &mut node.children {
+                children.shrink_to_fit();
+                children.remap(|id| {
+                    moved.push(old.take(id));
+                    NodeId::new(end + moved.len() - 1)
+                });
+            }
+            for slot in moved.drain(..) {
+                self.push(slot);
+            }
+            next += 1;
+        }
+        debug_assert_eq!(self.len, self.count);
+    }
+
+    /// marks every chunk as shared, first moving the slots into chunks
+    /// if the arena has not been shared before, and returns an arena
+    /// which shares them all. Either arena copies a chunk before
+    /// writing to it.
+    pub(crate) fn share(&mut self) -> Self
+    where
+        T: Clone,
+    {
+        if let Slots::Flat(slots) = &mut self.slots {
+            let mut slots = std::mem::take(slots).into_iter();
+            let chunks = (0..self.len.div_ceil(CHUNK))
+                .map(|_| Chunk::Owned(slots.by_ref().take(CHUNK).collect()))
+                .collect();
+            self.slots = Slots::Chunked {
+                chunks,
+                copy: |slots| slots.to_vec(),
+            };
+        }
+        if let Slots::Chunked { chunks, .. } = &mut self.slots {
+            for chunk in chunks {
+                if let Chunk::Owned(slots) = chunk {
+                    *chunk = Chunk::Shared(Arc::new(std::mem::take(slots)));
+                }
+            }
+        }
+        self.clone()
+    }
+
+    /// returns the number of nodes in use, including the root.
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    /// returns the bytes taken up by the slots, whether in use or not.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match &self.slots {
+            Slots::Flat(slots) => slots.capacity() * size_of::<Slot<T>>(),
+            Slots::Chunked { chunks, .. } => {
+                let slots: usize = chunks.iter().map(|chunk| chunk.capacity()).sum();
+                chunks.capacity() * size_of::<Chunk<T>>() + slots * size_of::<Slot<T>>()
+            }
+        }
+    }
+
+    /// stores node in a free slot, or a new one if there are none.
+    pub(crate) fn alloc(&mut self, node: RadixNode<T>) -> NodeId {
+        self.count += 1;
+        match self.free {
+            Some(id) => {
+                let slot = std::mem::replace(self.slot_mut(id.index()), Slot::Occupied(node));
+                let Slot::Free(next) = slot else {
+                    unreachable!("the free list only links free slots")
+                };
+                self.free = next;
+                id
+            }
+            None => self.push(Slot::Occupied(node)),
+        }
+    }
+
+    /// takes the node out of its slot, which is put on the free list.
+    pub(crate) fn free(&mut self, id: NodeId) -> RadixNode<T> {
+        debug_assert_ne!(id, ROOT, "the root is never freed");
+        let free = self.free;
+        let slot = std::mem::replace(self.slot_mut(id.index()), Slot::Free(free));
+        let Slot::Occupied(node) = slot else {
+            panic!("node {id:?} was freed twice")
+        };
+        self.free = Some(id);
+        self.count -= 1;
+        node
+    }
+
+    /// adds a slot after the last, starting a new chunk if need be.
+    fn push(&mut self, slot: Slot<T>) -> NodeId {
+        let index = self.len;
+        if let Slots::Chunked { chunks, .. } = &mut self.slots {
+            if index.is_multiple_of(CHUNK) {
+                chunks.push(Chunk::Owned(Vec::with_capacity(CHUNK)));
+            }
+        }
+        self.slots_mut(index).0.push(slot);
+        self.len += 1;
+        NodeId::new(index)
+    }
+
+    /// takes whatever is in the slot of id, leaving it free.
+    fn take(&mut self, id: NodeId) -> Slot<T> {
+        std::mem::replace(self.slot_mut(id.index()), Slot::Free(None))
+    }
+
+    fn slot(&self, index: usize) -> &Slot<T> {
+        match &self.slots {
+            Slots::Flat(slots) => &slots[index],
+            Slots::Chunked { chunks, .. } => &chunks[index / CHUNK][index % CHUNK],
+        }
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut Slot<T> {
+        let (slots, at) = self.slots_mut(index);
+        &mut slots[at]
+    }
+
+    /// returns the vector holding the slot at index for writing, with
+    /// the position of the slot in it. A shared chunk is copied first.
+    fn slots_mut(&mut self, index: usize) -> (&mut Vec<Slot<T>>, usize) {
+        match &mut self.slots {
+            Slots::Flat(slots) => (slots, index),
+            Slots::Chunked { chunks, copy } => {
+                let chunk = &mut chunks[index / CHUNK];
+                if let Chunk::Shared(shared) = chunk {
+                    *chunk = Chunk::Owned(unshare(shared, *copy));
+                }
+                let Chunk::Owned(slots) = chunk else {
+                    unreachable!("the chunk was just made owned")
+                };
+                (slots, index % CHUNK)
+            }
+        }
+    }
+}
+
+/// takes back the slots of a shared chunk if no snapshot
+/// holds them any more, or else copies them.
+#[cold]
+fn unshare<T>(shared: &mut Arc<Vec<Slot<T>>>, copy: CopySlots<T>) -> Vec<Slot<T>> {
+    match Arc::get_mut(shared) {
+        Some(slots) => std::mem::take(slots),
+        None => copy(shared),
+    }
+}
+
+impl<T> Deref for Chunk<T> {
+    type Target = Vec<Slot<T>>;
+
+    fn deref(&self) -> &Vec<Slot<T>> {
+        match self {
+            Chunk::Owned(slots) => slots,
+            Chunk::Shared(slots) => slots,
+        }
+    }
+}
+
+impl<T> Index<NodeId> for Arena<T> {
+    type Output = RadixNode<T>;
+
+    fn index(&self, id: NodeId) -> &RadixNode<T> {
+        match self.slot(id.index()) {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => panic!("node {id:?} has been freed"),
+        }
+    }
+}
+
+impl<T> IndexMut<NodeId> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut RadixNode<T> {
+        match self.slot_mut(id.index()) {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => panic!("node {id:?} has been freed"),
+        }
+    }
+}
+
+/// A mutable borrow of an arena through which several nodes can be
+/// borrowed at once, for walks which hand out a mutable reference to
+/// the value of every node they pass.
+pub(crate) struct ArenaMut<'a, T> {
+    /// the first slot of each chunk, or of all the slots
+    /// if the arena has not been shared.
+    chunks: Vec<*mut Slot<T>>,
+    /// the number of slots in each chunk.
+    chunk: usize,
+    len: usize,
+    marker: PhantomData<&'a mut Arena<T>>,
+}
+
+// SAFETY: an ArenaMut is equivalent to the &'a mut Arena<T> it was made
+// from, which is Send when T is.
+unsafe impl<T: Send> Send for ArenaMut<'_, T> {}
+
+// SAFETY: an ArenaMut is equivalent to the &'a mut Arena<T> it was made
+// from, which is Sync when T is.
+unsafe impl<T: Sync> Sync for ArenaMut<'_, T> {}
+
+impl<'a, T> ArenaMut<'a, T> {
+    /// borrows every slot of arena for writing, so any chunk shared
+    /// with a snapshot is copied here, before any node is handed out.
+    pub(crate) fn new(arena: &'a mut Arena<T>) -> Self {
+        let chunk = match arena.slots {
+            Slots::Flat(_) => usize::MAX,
+            Slots::Chunked { .. } => CHUNK,
+        };
+        let chunks = (0..arena.len)
+            .step_by(chunk)
+            .map(|index| arena.slots_mut(index).0.as_mut_ptr())
+            .collect();
+        Self {
+            chunks,
+            chunk,
+            len: arena.len,
+            marker: PhantomData,
+        }
+    }
+
+    fn slot(&self, id: NodeId) -> *mut Slot<T> {
+        let index = id.index();
+        assert!(index < self.len, "node {id:?} is out of bounds");
+        // SAFETY: the index is within the slots borrowed for 'a, and
+        // every chunk but the last is full.
+        unsafe { self.chunks[index / self.chunk].add(index % self.chunk) }
+    }
+
+    /// returns the node with this id.
+    ///
+    /// # Safety
+    ///
+    /// No reference returned by node_mut for the same id may be live.
+    pub(crate) unsafe fn node(&self, id: NodeId) -> &RadixNode<T> {
+        // SAFETY: the caller guarantees the node is not mutably borrowed.
+        match unsafe { &*self.slot(id) } {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => panic!("node {id:?} has been freed"),
+        }
+    }
+
+    /// returns the node with this id for the rest of the borrow.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at most once for each id, and no reference
+    /// returned by node for the same id may be live.
+    pub(crate) unsafe fn node_mut(&mut self, id: NodeId) -> &'a mut RadixNode<T> {
+        // SAFETY: the caller guarantees this is the only borrow of the node.
+        match unsafe { &mut *self.slot(id) } {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => panic!("node {id:?} has been freed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, Slots, ROOT};
+    use crate::children::Layout;
+
+    fn filled() -> Arena<u32> {
+        let mut arena = Arena::new(Layout::default());
+        for key in 0..200u32 {
+            arena.insert(&key.to_be_bytes(), key);
+        }
+        arena
+    }
+
+    #[test]
+    fn clear_keeps_the_capacity_of_a_flat_arena() {
+        let mut arena = filled();
+        let bytes = arena.heap_bytes();
+        arena.clear();
+        assert_eq!(arena.heap_bytes(), bytes);
+        assert_eq!(arena.count(), 1);
+        assert!(arena[ROOT].is_empty());
+        assert_eq!(arena.insert(b"a", 1), None);
+        assert_eq!(
+            arena.find(b"a").map(|node| arena[node].accept_state),
+            Some(Some(1))
+        );
+    }
+
+    #[test]
+    fn clear_leaves_shared_chunks_to_the_snapshot() {
+        let mut arena = filled();
+        let snapshot = arena.share();
+        arena.clear();
+        assert!(matches!(arena.slots, Slots::Flat(_)));
+        assert_eq!(arena.count(), 1);
+        assert_eq!(snapshot.count(), filled().count());
+        let node = snapshot
+            .find(&7u32.to_be_bytes())
+            .expect("the snapshot keeps its keys");
+        assert_eq!(snapshot[node].accept_state, Some(7));
+    }
+}
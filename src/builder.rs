@@ -0,0 +1,294 @@
+use std::cmp::Ordering;
+
+use crate::arena::{Arena, NodeId, ROOT};
+use crate::children::{Children, Layout};
+use crate::node::{common_prefix_len, RadixNode};
+use crate::RadixTrie;
+
+/// Builds a trie from keys pushed in ascending order, in one pass.
+/// The builder keeps the path to the last key pushed, so each key is
+/// attached below the deepest node it shares with the one before,
+/// without walking down from the root. The children of the nodes on
+/// that path are held aside until the path moves past them, when all
+/// of them are known and stored in a layout of the right size at once.
+///
+/// Keys out of order are still stored, but from then on every key is
+/// inserted from the root as by RadixTrie::insert.
+pub struct TrieBuilder<T, const BITS: usize = 8> {
+    arena: Arena<T>,
+    len: usize,
+    /// false once a key has come out of order.
+    sorted: bool,
+    /// the digits of the last key pushed.
+    last: Vec<u8>,
+    /// the nodes on the path to the last key, from the root down.
+    path: Vec<Level>,
+    /// the children of the nodes on the path, each node's
+    /// after those of the nodes above it, in order of their byte.
+    pending: Vec<(u8, NodeId)>,
+}
+
+/// A node on the path to the last key pushed.
+#[derive(Clone, Copy)]
+struct Level {
+    node: NodeId,
+    /// the number of digits of the key consumed to reach the node.
+    depth: usize,
+    /// the position of the node's first child in pending.
+    start: usize,
+}
+
+impl<T> TrieBuilder<T> {
+    /// returns a builder for a byte trie. As with RadixTrie::new, other
+    /// digit widths are built with default() or with_layout instead.
+    pub fn new() -> Self {
+        Self::with_layout(Layout::default())
+    }
+}
+
+impl<T, const BITS: usize> TrieBuilder<T, BITS> {
+    /// returns a builder for a trie whose nodes store their children
+    /// in layout. As with RadixTrie::with_layout, the digit width
+    /// is not inferred.
+    pub fn with_layout(layout: Layout) -> Self {
+        Self {
+            arena: Arena::new(layout),
+            len: 0,
+            sorted: true,
+            last: Vec::new(),
+            path: vec![Level {
+                node: ROOT,
+                depth: 0,
+                start: 0,
+            }],
+            pending: Vec::new(),
+        }
+    }
+
+    /// returns the number of distinct keys pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// stores value under key, which should be greater than the last
+    /// key pushed. If the key was already pushed, the previous value
+    /// is returned.
+    pub fn push(&mut self, key: impl AsRef<[u8]>, value: T) -> Option<T> {
+        let digits = RadixTrie::<T, BITS>::DIGITS.split(key.as_ref());
+        if !self.sorted {
+            return self.insert(&digits, value);
+        }
+        match (*digits).cmp(&self.last) {
+            Ordering::Less => {
+                self.finish_path();
+                self.sorted = false;
+                return self.insert(&digits, value);
+            }
+            Ordering::Equal => {
+                let level = self.path.last().expect("the root is never finished");
+                let prev = self.arena[level.node].accept_state.replace(value);
+                if prev.is_none() {
+                    self.len += 1;
+                }
+                return prev;
+            }
+            Ordering::Greater => {}
+        }
+
+        // • Finish the nodes below the point where key parts from
+        //   the last key. No later key can reach them.
+        let common = common_prefix_len(&self.last, &digits);
+        let mut lower = None;
+        while let Some(&level) = self.path.last() {
+            if level.depth <= common {
+                break;
+            }
+            self.path.pop();
+            self.finish(level);
+            lower = Some(level);
+        }
+
+        // • If key parts from the last key partway along an edge,
+        //   split it with a node at that point to hold both.
+        let upper = *self.path.last().expect("the root is never finished");
+        if let Some(lower) = lower.filter(|_| upper.depth < common) {
+            let label = self.last[upper.depth + 1..common].to_vec();
+            let middle = self.arena.alloc(RadixNode::with_label(label));
+            self.arena[lower.node].label.drain(..common - upper.depth);
+            let (_, child) = self.pending.last_mut().expect("lower was the last child");
+            *child = middle;
+            self.path.push(Level {
+                node: middle,
+                depth: common,
+                start: self.pending.len(),
+            });
+            self.pending.push((self.last[common], lower.node));
+        }
+
+        // • Add a leaf for the rest of key.
+        let leaf = self.arena.alloc(RadixNode {
+            label: digits[common + 1..].to_vec(),
+            accept_state: Some(value),
+            children: None,
+        });
+        self.pending.push((digits[common], leaf));
+        self.path.push(Level {
+            node: leaf,
+            depth: digits.len(),
+            start: self.pending.len(),
+        });
+        self.last.clear();
+        self.last.extend_from_slice(&digits);
+        self.len += 1;
+        None
+    }
+
+    /// returns the trie holding every key pushed.
+    pub fn build(mut self) -> RadixTrie<T, BITS> {
+        self.finish_path();
+        RadixTrie {
+            arena: self.arena,
+            len: self.len,
+        }
+    }
+
+    /// stores the children held aside for a node leaving the path.
+    fn finish(&mut self, level: Level) {
+        let children = &self.pending[level.start..];
+        if !children.is_empty() {
+            let layout = self.arena.layout;
+            self.arena[level.node].children = Some(Children::from_sorted(layout, children));
+        }
+        self.pending.truncate(level.start);
+    }
+
+    /// finishes every node on the path, leaving a complete trie.
+    fn finish_path(&mut self) {
+        while let Some(level) = self.path.pop() {
+            self.finish(level);
+        }
+    }
+
+    fn insert(&mut self, digits: &[u8], value: T) -> Option<T> {
+        let prev = self.arena.insert(digits, value);
+        if prev.is_none() {
+            self.len += 1;
+        }
+        prev
+    }
+}
+
+impl<T, const BITS: usize> Default for TrieBuilder<T, BITS> {
+    fn default() -> Self {
+        Self::with_layout(Layout::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Layout, RadixTrie, TrieBuilder};
+
+    /// a trie of the same entries made by inserting them one by one.
+    fn inserted<const BITS: usize>(
+        entries: &[(&[u8], u32)],
+        layout: Layout,
+    ) -> RadixTrie<u32, BITS> {
+        let mut trie = RadixTrie::with_layout(layout);
+        for &(key, value) in entries {
+            trie.insert(key, value);
+        }
+        trie
+    }
+
+    /// checks that two tries hold the same entries in the same
+    /// number of nodes.
+    fn assert_same<const BITS: usize>(a: &RadixTrie<u32, BITS>, b: &RadixTrie<u32, BITS>) {
+        assert_eq!(a.len(), b.len());
+        assert!(a.iter().eq(b.iter()));
+        assert_eq!(a.node_count(), b.node_count());
+    }
+
+    /// checks that pushing entries builds the same trie as inserting
+    /// them, down to the number of nodes, and that each push returns
+    /// what the insert would have.
+    fn check<const BITS: usize>(entries: &[(&[u8], u32)]) {
+        for layout in [Layout::Adaptive, Layout::Bitmap] {
+            let mut expected = RadixTrie::<u32, BITS>::with_layout(layout);
+            let mut builder = TrieBuilder::<u32, BITS>::with_layout(layout);
+            for &(key, value) in entries {
+                assert_eq!(builder.push(key, value), expected.insert(key, value));
+            }
+            assert_eq!(builder.len(), expected.len());
+            let built = builder.build();
+            assert_same(&built, &expected);
+            assert_same(&built, &inserted(entries, layout));
+        }
+    }
+
+    /// keys in ascending order, starting with the empty key. "abcx"
+    /// parts from "abcdefgh" partway along the edge into it, as do
+    /// "abd" from "abcx" and "b\0" from "b\0\0\0".
+    const SORTED: &[(&[u8], u32)] = &[
+        (b"", 0),
+        (b"abcdef", 1),
+        (b"abcdefgh", 2),
+        (b"abcx", 3),
+        (b"abd", 4),
+        (b"b\0\0\0", 5),
+        (b"b\0\x01", 6),
+        (b"c", 7),
+        (b"cat", 8),
+    ];
+
+    #[test]
+    fn sorted_keys_build_the_inserted_trie() {
+        check::<8>(SORTED);
+        check::<4>(SORTED);
+        check::<1>(SORTED);
+    }
+
+    #[test]
+    fn from_sorted_iter_builds_the_inserted_trie() {
+        let trie = RadixTrie::<_>::from_sorted_iter(SORTED.iter().copied());
+        assert_same(&trie, &inserted::<8>(SORTED, Layout::Adaptive));
+    }
+
+    #[test]
+    fn duplicate_keys_keep_the_later_value() {
+        let entries: &[(&[u8], u32)] = &[
+            (b"", 0),
+            (b"", 1),
+            (b"ab", 2),
+            (b"ab", 3),
+            (b"abc", 4),
+            (b"abc", 5),
+        ];
+        check::<8>(entries);
+        let trie = RadixTrie::<_>::from_sorted_iter(entries.iter().copied());
+        assert_eq!(trie.len(), 3);
+        assert_eq!(trie.get(b""), Some(&1));
+        assert_eq!(trie.get(b"ab"), Some(&3));
+        assert_eq!(trie.get(b"abc"), Some(&5));
+    }
+
+    #[test]
+    fn keys_out_of_order_are_inserted() {
+        let mut entries = SORTED.to_vec();
+        // sorted up to "abd", then earlier keys, new and repeated,
+        // then later ones again
+        entries.insert(5, (b"abce", 9));
+        entries.insert(6, (b"", 10));
+        entries.insert(7, (b"abcdef", 11));
+        entries.insert(8, (b"a", 12));
+        check::<8>(&entries);
+        check::<1>(&entries);
+        entries.reverse();
+        check::<8>(&entries);
+        let trie = RadixTrie::<_>::from_sorted_iter(entries.iter().copied());
+        assert_same(&trie, &inserted::<8>(&entries, Layout::Adaptive));
+    }
+}
@@ -0,0 +1,695 @@
+use std::alloc;
+use std::iter::Zip;
+use std::ops::Range;
+use std::slice;
+
+use crate::arena::NodeId;
+use crate::node::BRANCH_FACTOR;
+
+/// How the children of each node in a trie are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Layout {
+    /// Node4, Node16, Node48 or Node256 depending on how many
+    /// children a node has, as in the Adaptive Radix Tree.
+    #[default]
+    Adaptive,
+    /// a 256-bit map of which bytes have a child, plus a vector
+    /// holding only those children, located by counting the set
+    /// bits below their byte. Smaller than Adaptive for most
+    /// fanouts, at the cost of shifting children on insertion.
+    Bitmap,
+}
+
+/// The children of a node. Under Layout::Adaptive they are stored in
+/// one of four layouts depending on how many there are, as in the
+/// Adaptive Radix Tree. A layout grows into the next one up once it is
+/// full, and shrinks back once removals leave it mostly empty, so
+/// sparse nodes stay small. Under Layout::Bitmap every node uses
+/// a Bitmap, whatever its fanout.
+#[derive(Clone)]
+pub(crate) enum Children {
+    /// up to 4 children, with their bytes kept sorted alongside.
+    Node4(Sorted<4>),
+    /// up to 16 children, laid out like Node4.
+    Node16(Sorted<16>),
+    /// up to 48 children in no particular order, located
+    /// through an index from each byte to its child.
+    Node48(Box<Indexed>),
+    /// a slot for every possible byte.
+    Node256(Box<Full>),
+    /// only the children present, in order of their byte.
+    Bitmap(Bitmap),
+}
+
+#[derive(Clone)]
+pub(crate) struct Sorted<const N: usize> {
+    /// the bytes of the children, in ascending order.
+    /// Only the first len are in use.
+    keys: [u8; N],
+    /// the children, in the order of their bytes.
+    nodes: [Option<NodeId>; N],
+    len: u8,
+}
+
+#[derive(Clone)]
+pub(crate) struct Indexed {
+    /// for each byte, one more than the position of its child
+    /// in nodes, or 0 if it has none.
+    index: [u8; BRANCH_FACTOR],
+    /// the children, with the first len in use.
+    nodes: [Option<NodeId>; NODE48_CAPACITY],
+    len: u8,
+}
+
+#[derive(Clone)]
+pub(crate) struct Full {
+    len: usize,
+    slots: [Option<NodeId>; BRANCH_FACTOR],
+}
+
+#[derive(Clone)]
+pub(crate) struct Bitmap {
+    /// bit b of the map is set if byte b has a child.
+    bits: [u64; WORDS],
+    /// the children in ascending order of their byte, so a child's
+    /// position is the number of bits set below its byte.
+    nodes: Vec<NodeId>,
+}
+
+/// the number of words in the bitmap of a Bitmap.
+const WORDS: usize = BRANCH_FACTOR / 64;
+
+/// the most children a Node48 can hold.
+const NODE48_CAPACITY: usize = 48;
+
+/// Each layout shrinks once it holds this few children. The margin
+/// below the capacity of the next layout down keeps a node which
+/// hovers around the boundary from converting back and forth.
+const NODE16_SHRINK: usize = 3;
+const NODE48_SHRINK: usize = 12;
+const NODE256_SHRINK: usize = 36;
+
+impl Children {
+    pub(crate) fn new(layout: Layout) -> Self {
+        match layout {
+            Layout::Adaptive => Children::Node4(Sorted::new()),
+            Layout::Bitmap => Children::Bitmap(Bitmap::new()),
+        }
+    }
+
+    /// returns an empty layout with room for count children.
+    fn with_room_for(count: usize) -> Self {
+        match count {
+            0..=4 => Children::Node4(Sorted::new()),
+            5..=16 => Children::Node16(Sorted::new()),
+            17..=NODE48_CAPACITY => Children::Node48(Box::new(Indexed::new())),
+            _ => Children::Node256(Full::new()),
+        }
+    }
+
+    /// returns children holding exactly those given, which must be
+    /// in ascending order of their byte, in the smallest layout
+    /// with room for all of them.
+    pub(crate) fn from_sorted(layout: Layout, children: &[(u8, NodeId)]) -> Self {
+        let mut sorted = match layout {
+            Layout::Adaptive => Self::with_room_for(children.len()),
+            Layout::Bitmap => Children::Bitmap(Bitmap {
+                bits: [0; WORDS],
+                nodes: Vec::with_capacity(children.len()),
+            }),
+        };
+        for &(byte, node) in children {
+            sorted.insert(byte, node);
+        }
+        sorted
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Children::Node4(sorted) => sorted.len(),
+            Children::Node16(sorted) => sorted.len(),
+            Children::Node48(indexed) => indexed.len(),
+            Children::Node256(full) => full.len,
+            Children::Bitmap(bitmap) => bitmap.nodes.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns the bytes the children take up on the heap,
+    /// not counting the nodes they refer to. Node4 and Node16
+    /// are held inline, so they take up none.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match self {
+            Children::Node4(_) | Children::Node16(_) => 0,
+            Children::Node48(_) => size_of::<Indexed>(),
+            Children::Node256(_) => size_of::<Full>(),
+            Children::Bitmap(bitmap) => bitmap.nodes.capacity() * size_of::<NodeId>(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self {
+            Children::Node4(sorted) => sorted.len() == 4,
+            Children::Node16(sorted) => sorted.len() == 16,
+            Children::Node48(indexed) => indexed.len() == NODE48_CAPACITY,
+            Children::Node256(_) | Children::Bitmap(_) => false,
+        }
+    }
+
+    pub(crate) fn get(&self, byte: u8) -> Option<NodeId> {
+        match self {
+            Children::Node4(sorted) => sorted.get(byte),
+            Children::Node16(sorted) => sorted.get(byte),
+            Children::Node48(indexed) => indexed.get(byte),
+            Children::Node256(full) => full.slots[byte as usize],
+            Children::Bitmap(bitmap) => bitmap.get(byte),
+        }
+    }
+
+    /// adds a child under a byte which has none yet,
+    /// growing into a larger layout if this one is full.
+    pub(crate) fn insert(&mut self, byte: u8, node: NodeId) {
+        if self.is_full() {
+            self.convert(self.len() + 1);
+        }
+        match self {
+            Children::Node4(sorted) => sorted.insert(byte, node),
+            Children::Node16(sorted) => sorted.insert(byte, node),
+            Children::Node48(indexed) => indexed.insert(byte, node),
+            Children::Node256(full) => {
+                full.slots[byte as usize] = Some(node);
+                full.len += 1;
+            }
+            Children::Bitmap(bitmap) => bitmap.insert(byte, node),
+        }
+    }
+
+    /// removes the child under byte, shrinking into a
+    /// smaller layout if few enough children remain.
+    pub(crate) fn remove(&mut self, byte: u8) -> Option<NodeId> {
+        let (removed, shrink) = match self {
+            Children::Node4(sorted) => (sorted.remove(byte), false),
+            Children::Node16(sorted) => {
+                let removed = sorted.remove(byte);
+                (removed, sorted.len() <= NODE16_SHRINK)
+            }
+            Children::Node48(indexed) => {
+                let removed = indexed.remove(byte);
+                (removed, indexed.len() <= NODE48_SHRINK)
+            }
+            Children::Node256(full) => {
+                let removed = full.slots[byte as usize].take();
+                if removed.is_some() {
+                    full.len -= 1;
+                }
+                (removed, full.len <= NODE256_SHRINK)
+            }
+            Children::Bitmap(bitmap) => (bitmap.remove(byte), false),
+        };
+        if shrink {
+            self.convert(self.len());
+        }
+        removed
+    }
+
+    /// moves the children into the smallest adaptive
+    /// layout with room for count of them.
+    fn convert(&mut self, count: usize) {
+        let children = std::mem::replace(self, Children::new(Layout::Adaptive));
+        let mut converted = Self::with_room_for(count);
+        for (byte, node) in children.range(0..BRANCH_FACTOR) {
+            converted.insert(byte as u8, node);
+        }
+        *self = converted;
+    }
+
+    /// moves the children into the smallest layout which holds
+    /// them, and gives back any room kept for more.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let len = self.len();
+        let fits = match self {
+            Children::Node4(_) | Children::Bitmap(_) => true,
+            Children::Node16(_) => len > 4,
+            Children::Node48(_) => len > 16,
+            Children::Node256(_) => len > NODE48_CAPACITY,
+        };
+        if !fits {
+            self.convert(len);
+        }
+        if let Children::Bitmap(bitmap) = self {
+            bitmap.nodes.shrink_to_fit();
+        }
+    }
+
+    /// replaces the id of every child with f applied to it.
+    pub(crate) fn remap(&mut self, mut f: impl FnMut(NodeId) -> NodeId) {
+        let slots: &mut [Option<NodeId>] = match self {
+            Children::Node4(sorted) => &mut sorted.nodes,
+            Children::Node16(sorted) => &mut sorted.nodes,
+            Children::Node48(indexed) => &mut indexed.nodes,
+            Children::Node256(full) => &mut full.slots,
+            Children::Bitmap(bitmap) => {
+                for node in &mut bitmap.nodes {
+                    *node = f(*node);
+                }
+                return;
+            }
+        };
+        for node in slots.iter_mut().flatten() {
+            *node = f(*node);
+        }
+    }
+
+    /// returns the smallest byte with a child.
+    pub(crate) fn first_byte(&self) -> Option<u8> {
+        self.range(0..BRANCH_FACTOR)
+            .next()
+            .map(|(byte, _)| byte as u8)
+    }
+
+    /// returns the children whose byte falls within slots, in order.
+    pub(crate) fn range(&self, slots: Range<usize>) -> ChildRange<'_> {
+        match self {
+            Children::Node4(sorted) => sorted.range(slots),
+            Children::Node16(sorted) => sorted.range(slots),
+            Children::Node48(indexed) => ChildRange::Indexed {
+                bytes: slots,
+                indexed,
+            },
+            Children::Node256(full) => {
+                ChildRange::Full(slots.clone().zip(full.slots[slots].iter()))
+            }
+            Children::Bitmap(bitmap) => {
+                let positions = bitmap.positions(slots.clone());
+                ChildRange::Bitmap(bitmap.bytes(slots).zip(bitmap.nodes[positions].iter()))
+            }
+        }
+    }
+}
+
+impl<const N: usize> Sorted<N> {
+    fn new() -> Self {
+        Self {
+            keys: [0; N],
+            nodes: [None; N],
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn keys(&self) -> &[u8] {
+        &self.keys[..self.len()]
+    }
+
+    fn get(&self, byte: u8) -> Option<NodeId> {
+        let position = self.keys().binary_search(&byte).ok()?;
+        self.nodes[position]
+    }
+
+    fn insert(&mut self, byte: u8, node: NodeId) {
+        let len = self.len();
+        let position = self.keys().partition_point(|&key| key < byte);
+        self.keys.copy_within(position..len, position + 1);
+        self.nodes.copy_within(position..len, position + 1);
+        self.keys[position] = byte;
+        self.nodes[position] = Some(node);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, byte: u8) -> Option<NodeId> {
+        let len = self.len();
+        let position = self.keys().binary_search(&byte).ok()?;
+        let removed = self.nodes[position].take();
+        self.keys.copy_within(position + 1..len, position);
+        self.nodes.copy_within(position + 1..len, position);
+        self.nodes[len - 1] = None;
+        self.len -= 1;
+        removed
+    }
+
+    /// returns the positions of the children whose byte falls within slots.
+    fn positions(&self, slots: Range<usize>) -> Range<usize> {
+        let keys = self.keys();
+        let start = keys.partition_point(|&key| (key as usize) < slots.start);
+        let end = keys.partition_point(|&key| (key as usize) < slots.end);
+        start..end.max(start)
+    }
+
+    fn range(&self, slots: Range<usize>) -> ChildRange<'_> {
+        let positions = self.positions(slots);
+        ChildRange::Sorted(
+            self.keys[positions.clone()]
+                .iter()
+                .zip(self.nodes[positions].iter()),
+        )
+    }
+}
+
+impl Indexed {
+    fn new() -> Self {
+        Self {
+            index: [0; BRANCH_FACTOR],
+            nodes: [None; NODE48_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn get(&self, byte: u8) -> Option<NodeId> {
+        match self.index[byte as usize] {
+            0 => None,
+            slot => self.nodes[slot as usize - 1],
+        }
+    }
+
+    fn insert(&mut self, byte: u8, node: NodeId) {
+        self.nodes[self.len()] = Some(node);
+        self.len += 1;
+        self.index[byte as usize] = self.len;
+    }
+
+    fn remove(&mut self, byte: u8) -> Option<NodeId> {
+        let slot = std::mem::replace(&mut self.index[byte as usize], 0);
+        if slot == 0 {
+            return None;
+        }
+        // The last child moves into the freed position.
+        let last = self.len;
+        if slot != last {
+            if let Some(moved) = self.index.iter_mut().find(|entry| **entry == last) {
+                *moved = slot;
+            }
+        }
+        self.len -= 1;
+        self.nodes.swap(slot as usize - 1, last as usize - 1);
+        self.nodes[last as usize - 1].take()
+    }
+}
+
+impl Full {
+    /// allocates a node with every slot empty. An empty node is all
+    /// zero bits, so it is taken straight from a zeroed allocation
+    /// rather than built up and moved into place.
+    fn new() -> Box<Self> {
+        let layout = alloc::Layout::new::<Self>();
+        // SAFETY: the layout is not zero-sized. A len of 0 is zero, and
+        // None is represented by zero for an Option<NodeId>, as NodeId
+        // is a transparent NonZeroU32.
+        unsafe {
+            let full = alloc::alloc_zeroed(layout);
+            if full.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            Box::from_raw(full.cast())
+        }
+    }
+}
+
+impl Bitmap {
+    fn new() -> Self {
+        Self {
+            bits: [0; WORDS],
+            nodes: Vec::new(),
+        }
+    }
+
+    fn contains(&self, byte: u8) -> bool {
+        self.bits[byte as usize / 64] & (1 << (byte % 64)) != 0
+    }
+
+    /// returns the number of children whose byte is below slot.
+    fn rank(&self, slot: usize) -> usize {
+        self.bits
+            .iter()
+            .zip(below(slot))
+            .map(|(bits, mask)| (bits & mask).count_ones() as usize)
+            .sum()
+    }
+
+    fn get(&self, byte: u8) -> Option<NodeId> {
+        if !self.contains(byte) {
+            return None;
+        }
+        Some(self.nodes[self.rank(byte as usize)])
+    }
+
+    fn insert(&mut self, byte: u8, node: NodeId) {
+        let position = self.rank(byte as usize);
+        self.bits[byte as usize / 64] |= 1 << (byte % 64);
+        self.nodes.insert(position, node);
+    }
+
+    fn remove(&mut self, byte: u8) -> Option<NodeId> {
+        if !self.contains(byte) {
+            return None;
+        }
+        self.bits[byte as usize / 64] &= !(1 << (byte % 64));
+        Some(self.nodes.remove(self.rank(byte as usize)))
+    }
+
+    /// returns the positions of the children whose byte falls within slots.
+    fn positions(&self, slots: Range<usize>) -> Range<usize> {
+        let start = self.rank(slots.start);
+        start..self.rank(slots.end).max(start)
+    }
+
+    /// returns the bytes with a child which fall within slots.
+    fn bytes(&self, slots: Range<usize>) -> SetBits {
+        let mut bits = self.bits;
+        for ((bits, end), start) in bits
+            .iter_mut()
+            .zip(below(slots.end))
+            .zip(below(slots.start))
+        {
+            *bits &= end & !start;
+        }
+        SetBits { bits }
+    }
+}
+
+/// returns a bitmap with every bit below slot set.
+fn below(slot: usize) -> [u64; WORDS] {
+    let mut mask = [0; WORDS];
+    for (word, bits) in mask.iter_mut().enumerate() {
+        *bits = match slot.saturating_sub(word * 64) {
+            0 => 0,
+            1..=63 => (1 << (slot - word * 64)) - 1,
+            _ => !0,
+        };
+    }
+    mask
+}
+
+/// The set bits of a bitmap, as bytes in ascending order.
+pub(crate) struct SetBits {
+    bits: [u64; WORDS],
+}
+
+impl Iterator for SetBits {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let (word, bits) = self
+            .bits
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != 0)?;
+        let bit = bits.trailing_zeros() as usize;
+        *bits &= *bits - 1;
+        Some(word * 64 + bit)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self
+            .bits
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum();
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for SetBits {
+    fn next_back(&mut self) -> Option<usize> {
+        let (word, bits) = self
+            .bits
+            .iter_mut()
+            .enumerate()
+            .rfind(|(_, bits)| **bits != 0)?;
+        let bit = 63 - bits.leading_zeros() as usize;
+        *bits &= !(1 << bit);
+        Some(word * 64 + bit)
+    }
+}
+
+impl ExactSizeIterator for SetBits {}
+
+/// The children of a node whose byte falls within a range, in
+/// order, as handed out by Children::range.
+pub(crate) enum ChildRange<'a> {
+    Empty,
+    Sorted(Zip<slice::Iter<'a, u8>, slice::Iter<'a, Option<NodeId>>>),
+    Indexed {
+        bytes: Range<usize>,
+        indexed: &'a Indexed,
+    },
+    Full(Zip<Range<usize>, slice::Iter<'a, Option<NodeId>>>),
+    Bitmap(Zip<SetBits, slice::Iter<'a, NodeId>>),
+}
+
+impl<'a> Iterator for ChildRange<'a> {
+    type Item = (usize, NodeId);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ChildRange::Empty => None,
+            ChildRange::Sorted(children) => {
+                let (&byte, &node) = children.next()?;
+                Some((byte as usize, node?))
+            }
+            ChildRange::Indexed { bytes, indexed } => {
+                bytes.find_map(|byte| Some((byte, indexed.get(byte as u8)?)))
+            }
+            ChildRange::Full(slots) => slots.find_map(|(byte, slot)| Some((byte, (*slot)?))),
+            ChildRange::Bitmap(children) => {
+                let (byte, &node) = children.next()?;
+                Some((byte, node))
+            }
+        }
+    }
+}
+
+impl DoubleEndedIterator for ChildRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            ChildRange::Empty => None,
+            ChildRange::Sorted(children) => {
+                let (&byte, &node) = children.next_back()?;
+                Some((byte as usize, node?))
+            }
+            ChildRange::Indexed { bytes, indexed } => bytes
+                .rev()
+                .find_map(|byte| Some((byte, indexed.get(byte as u8)?))),
+            ChildRange::Full(slots) => slots.rev().find_map(|(byte, slot)| Some((byte, (*slot)?))),
+            ChildRange::Bitmap(children) => {
+                let (byte, &node) = children.next_back()?;
+                Some((byte, node))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Children, Layout};
+    use crate::arena::{Arena, NodeId};
+    use crate::node::{RadixNode, BRANCH_FACTOR};
+
+    fn layout(children: &Children) -> &'static str {
+        match children {
+            Children::Node4(_) => "Node4",
+            Children::Node16(_) => "Node16",
+            Children::Node48(_) => "Node48",
+            Children::Node256(_) => "Node256",
+            Children::Bitmap(_) => "Bitmap",
+        }
+    }
+
+    /// a distinct node id for every byte.
+    fn ids() -> Vec<NodeId> {
+        let mut arena = Arena::<()>::new(Layout::Adaptive);
+        (0..BRANCH_FACTOR)
+            .map(|_| arena.alloc(RadixNode::new()))
+            .collect()
+    }
+
+    /// checks that exactly the bytes in present have a child,
+    /// and that range visits them in order from either end.
+    fn check(children: &Children, ids: &[NodeId], present: &[u8]) {
+        assert_eq!(children.len(), present.len());
+        for byte in 0..=255u8 {
+            let expected = present.contains(&byte).then_some(ids[byte as usize]);
+            let found = children.get(byte);
+            assert_eq!(found, expected, "byte {byte} in {}", layout(children));
+        }
+        let mut sorted = present.to_vec();
+        sorted.sort();
+        let bytes: Vec<_> = children
+            .range(0..BRANCH_FACTOR)
+            .map(|(byte, node)| {
+                assert_eq!(node, ids[byte]);
+                byte as u8
+            })
+            .collect();
+        assert_eq!(bytes, sorted);
+        let mut reversed: Vec<_> = children
+            .range(0..BRANCH_FACTOR)
+            .rev()
+            .map(|(byte, _)| byte as u8)
+            .collect();
+        reversed.reverse();
+        assert_eq!(reversed, sorted);
+        assert_eq!(children.first_byte(), sorted.first().copied());
+    }
+
+    #[test]
+    fn one_node_grows_through_every_layout_and_shrinks_back() {
+        // every byte once, in an order unrelated to their values
+        let order: Vec<u8> = (0..=255u8).map(|i| i.wrapping_mul(37) ^ 0x5a).collect();
+        let ids = ids();
+        let mut children = Children::new(Layout::Adaptive);
+        for (count, &byte) in (1..).zip(&order) {
+            children.insert(byte, ids[byte as usize]);
+            let expected = match count {
+                ..=4 => "Node4",
+                5..=16 => "Node16",
+                17..=48 => "Node48",
+                _ => "Node256",
+            };
+            assert_eq!(layout(&children), expected, "after {count} inserts");
+            check(&children, &ids, &order[..count]);
+        }
+
+        for (removed, &byte) in (1..).zip(&order) {
+            assert_eq!(children.remove(byte), Some(ids[byte as usize]));
+            assert!(children.remove(byte).is_none());
+            let count = BRANCH_FACTOR - removed;
+            let expected = match count {
+                ..=3 => "Node4",
+                4..=12 => "Node16",
+                13..=36 => "Node48",
+                _ => "Node256",
+            };
+            assert_eq!(layout(&children), expected, "with {count} left");
+            check(&children, &ids, &order[removed..]);
+        }
+        assert!(children.is_empty());
+    }
+
+    #[test]
+    fn a_bitmap_node_stays_a_bitmap() {
+        let order: Vec<u8> = (0..=255u8).map(|i| i.wrapping_mul(37) ^ 0x5a).collect();
+        let ids = ids();
+        let mut children = Children::new(Layout::Bitmap);
+        for (count, &byte) in (1..).zip(&order) {
+            children.insert(byte, ids[byte as usize]);
+            assert_eq!(layout(&children), "Bitmap");
+            check(&children, &ids, &order[..count]);
+        }
+        for (removed, &byte) in (1..).zip(&order) {
+            assert_eq!(children.remove(byte), Some(ids[byte as usize]));
+            assert!(children.remove(byte).is_none());
+            assert_eq!(layout(&children), "Bitmap");
+            check(&children, &ids, &order[removed..]);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+/// The digits a trie branches on. Each byte of a key is split into
+/// 8 / bits digits of bits bits each, most significant first, so the
+/// digit strings of two keys compare the same way as the keys do, and
+/// one key is a prefix of another exactly when its digits are.
+#[derive(Clone, Copy)]
+pub(crate) struct Digits {
+    bits: usize,
+}
+
+impl Digits {
+    pub(crate) const fn new(bits: usize) -> Self {
+        assert!(
+            matches!(bits, 1 | 2 | 4 | 8),
+            "the digits of a RadixTrie must be 1, 2, 4 or 8 bits wide"
+        );
+        Self { bits }
+    }
+
+    /// returns the number of distinct digits, and so the
+    /// most children a node can have.
+    pub(crate) const fn radix(self) -> usize {
+        1 << self.bits
+    }
+
+    /// returns the digits of key.
+    pub(crate) fn split(self, key: &[u8]) -> Cow<'_, [u8]> {
+        if self.bits == 8 {
+            return Cow::Borrowed(key);
+        }
+        let per_byte = 8 / self.bits;
+        let mask = (1 << self.bits) - 1;
+        let digits = key
+            .iter()
+            .flat_map(|&byte| {
+                (0..per_byte)
+                    .rev()
+                    .map(move |i| (byte >> (i * self.bits)) & mask)
+            })
+            .collect();
+        Cow::Owned(digits)
+    }
+
+    /// returns the key whose digits are digits, which must
+    /// make up a whole number of bytes.
+    pub(crate) fn join(self, digits: &[u8]) -> Vec<u8> {
+        if self.bits == 8 {
+            return digits.to_vec();
+        }
+        digits
+            .chunks(8 / self.bits)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |byte, &digit| byte << self.bits | digit)
+            })
+            .collect()
+    }
+
+    /// returns the number of bytes taken up by count digits.
+    pub(crate) fn bytes(self, count: usize) -> usize {
+        count * self.bits / 8
+    }
+}
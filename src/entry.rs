@@ -0,0 +1,327 @@
+use std::borrow::Cow;
+
+use crate::arena::{Arena, NodeId, ROOT};
+use crate::{RadixNode, RadixTrie};
+
+/// A view into a single key of a trie, which is either occupied
+/// or vacant. Constructed with RadixTrie::entry.
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+
+/// An entry whose key holds a value.
+pub struct OccupiedEntry<'a, T> {
+    key: &'a [u8],
+    /// the digits of key, which the trie branches on.
+    digits: Cow<'a, [u8]>,
+    /// the node whose accept state holds the value.
+    node: NodeId,
+    arena: &'a mut Arena<T>,
+    len: &'a mut usize,
+}
+
+/// An entry whose key holds no value.
+pub struct VacantEntry<'a, T> {
+    key: &'a [u8],
+    /// the digits of key, which the trie branches on.
+    digits: Cow<'a, [u8]>,
+    /// the deepest node along the key's path whose
+    /// key is entirely a prefix of it.
+    node: NodeId,
+    /// the number of digits of key consumed to reach node.
+    depth: usize,
+    arena: &'a mut Arena<T>,
+    len: &'a mut usize,
+}
+
+impl<'a, T> Entry<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a mut RadixTrie<T, BITS>, key: &'a [u8]) -> Self {
+        let digits = RadixTrie::<T, BITS>::DIGITS.split(key);
+        let RadixTrie { arena, len } = trie;
+        let mut node = ROOT;
+        let mut depth = 0;
+        while let Some(&byte) = digits.get(depth) {
+            let tail = &digits[depth + 1..];
+            let Some(child) = arena[node].child(byte) else {
+                break;
+            };
+            if !leads_to(&arena[child], tail) {
+                break;
+            }
+            depth += 1 + arena[child].label.len();
+            node = child;
+        }
+
+        if depth == digits.len() && arena[node].accept_state.is_some() {
+            Entry::Occupied(OccupiedEntry {
+                key,
+                digits,
+                node,
+                arena,
+                len,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                key,
+                digits,
+                node,
+                depth,
+                arena,
+                len,
+            })
+        }
+    }
+
+    /// returns the key this entry was created for.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// inserts default if the entry is vacant and returns
+    /// a mutable reference to the value.
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// like or_insert, but only computes the value if the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// like or_insert_with, but the function receives the key.
+    pub fn or_insert_with_key<F: FnOnce(&[u8]) -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// applies f to the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(ref mut entry) = self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+/// returns true if the walk towards tail, the rest of a key after
+/// the byte selecting child, can carry on through child.
+fn leads_to<T>(child: &RadixNode<T>, tail: &[u8]) -> bool {
+    !child.is_empty() && tail.starts_with(&child.label)
+}
+
+impl<'a, T: Default> Entry<'a, T> {
+    /// inserts T::default() if the entry is vacant and returns
+    /// a mutable reference to the value.
+    pub fn or_default(self) -> &'a mut T {
+        self.or_insert_with(T::default)
+    }
+}
+
+impl<'a, T> OccupiedEntry<'a, T> {
+    pub fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    pub fn get(&self) -> &T {
+        self.arena[self.node]
+            .accept_state
+            .as_ref()
+            .expect("occupied entry holds a value")
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.arena[self.node]
+            .accept_state
+            .as_mut()
+            .expect("occupied entry holds a value")
+    }
+
+    /// converts the entry into a mutable reference to its value
+    /// which lives as long as the borrow of the trie.
+    pub fn into_mut(self) -> &'a mut T {
+        self.arena[self.node]
+            .accept_state
+            .as_mut()
+            .expect("occupied entry holds a value")
+    }
+
+    /// replaces the value and returns the previous one.
+    pub fn insert(&mut self, value: T) -> T {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// removes the value from the trie and returns it.
+    pub fn remove(self) -> T {
+        self.remove_entry().1
+    }
+
+    /// removes the value from the trie and returns it along with the key.
+    pub fn remove_entry(self) -> (Vec<u8>, T) {
+        // The walk from the root prunes the nodes along the key's path.
+        let value = self
+            .arena
+            .remove(&self.digits)
+            .expect("occupied entry holds a value");
+        *self.len -= 1;
+        (self.key.to_vec(), value)
+    }
+}
+
+impl<'a, T> VacantEntry<'a, T> {
+    pub fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    /// stores value under the entry's key and returns a
+    /// mutable reference to it.
+    pub fn insert(self, value: T) -> &'a mut T {
+        let node = self
+            .arena
+            .find_or_create(self.node, &self.digits[self.depth..]);
+        *self.len += 1;
+        self.arena[node].accept_state.insert(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Entry, RadixTrie};
+
+    fn sample() -> RadixTrie<u32> {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"car".to_vec(), 1);
+        trie.insert(b"cart".to_vec(), 2);
+        trie.insert(b"cat".to_vec(), 3);
+        trie
+    }
+
+    #[test]
+    fn or_insert_fills_only_vacant_entries() {
+        let mut trie = sample();
+        *trie.entry(b"car").or_insert(10) += 1;
+        assert_eq!(trie.get(b"car"), Some(&2));
+        assert_eq!(trie.len(), 3);
+        *trie.entry(b"cab").or_insert(10) += 1;
+        assert_eq!(trie.get(b"cab"), Some(&11));
+        assert_eq!(trie.len(), 4);
+    }
+
+    #[test]
+    fn or_insert_with_runs_only_for_vacant_entries() {
+        let mut trie = sample();
+        trie.entry(b"cat").or_insert_with(|| unreachable!());
+        assert_eq!(trie.entry(b"dog").or_insert_with(|| 4), &mut 4);
+        assert_eq!(trie.len(), 4);
+        let value = trie
+            .entry(b"horse")
+            .or_insert_with_key(|key| key.len() as u32);
+        assert_eq!(*value, 5);
+        trie.entry(b"horse").or_insert_with_key(|_| unreachable!());
+        assert_eq!(trie.get(b"horse"), Some(&5));
+        assert_eq!(trie.len(), 5);
+    }
+
+    #[test]
+    fn or_default_and_and_modify() {
+        let mut trie = sample();
+        trie.entry(b"").or_default();
+        assert_eq!(trie.get(b""), Some(&0));
+        assert_eq!(trie.len(), 4);
+        trie.entry(b"cart").and_modify(|v| *v *= 10).or_default();
+        assert_eq!(trie.get(b"cart"), Some(&20));
+        trie.entry(b"carts")
+            .and_modify(|_| unreachable!())
+            .or_insert(7);
+        assert_eq!(trie.get(b"carts"), Some(&7));
+        assert_eq!(trie.len(), 5);
+    }
+
+    #[test]
+    fn occupied_entry_insert_and_remove() {
+        let mut trie = sample();
+        let Entry::Occupied(mut entry) = trie.entry(b"car") else {
+            panic!("car is occupied");
+        };
+        assert_eq!(entry.key(), b"car");
+        assert_eq!(entry.get(), &1);
+        assert_eq!(entry.insert(5), 1);
+        *entry.get_mut() += 1;
+        assert_eq!(entry.remove(), 6);
+        assert_eq!(trie.get(b"car"), None);
+        assert_eq!(trie.get(b"cart"), Some(&2));
+        assert_eq!(trie.len(), 2);
+
+        let Entry::Occupied(entry) = trie.entry(b"cart") else {
+            panic!("cart is occupied");
+        };
+        assert_eq!(entry.remove_entry(), (b"cart".to_vec(), 2));
+        assert_eq!(trie.len(), 1);
+        let Entry::Occupied(entry) = trie.entry(b"cat") else {
+            panic!("cat is occupied");
+        };
+        *entry.into_mut() = 30;
+        assert_eq!(trie.remove_entry(b"cat"), Some((b"cat".to_vec(), 30)));
+        assert!(trie.is_empty());
+        assert_eq!(trie.node_count(), 1);
+    }
+
+    #[test]
+    fn vacant_entry_insert() {
+        let mut trie = sample();
+        // "ca" is a prefix of stored keys and "cb" leaves the path of
+        // "car" partway, so both are vacant.
+        for (key, value) in [(&b"ca"[..], 4), (b"cb", 5), (b"carts", 6), (b"", 7)] {
+            let Entry::Vacant(entry) = trie.entry(key) else {
+                panic!("{key:?} is vacant");
+            };
+            assert_eq!(entry.key(), key);
+            assert_eq!(*entry.insert(value), value);
+        }
+        assert_eq!(trie.len(), 7);
+        for (key, value) in [(&b"ca"[..], 4), (b"cb", 5), (b"carts", 6), (b"", 7)] {
+            assert_eq!(trie.get(key), Some(&value));
+        }
+        assert_eq!(trie.get(b"car"), Some(&1));
+        assert_eq!(trie.get(b"cart"), Some(&2));
+    }
+
+    #[test]
+    fn vacant_entry_insert_inside_a_label() {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"interstellar".to_vec(), 1);
+        // "inter" ends partway along the label of "interstellar",
+        // and "intern" parts from it there.
+        for (key, value) in [(&b"inter"[..], 2), (b"intern", 3), (b"i", 4)] {
+            let Entry::Vacant(entry) = trie.entry(key) else {
+                panic!("{key:?} is vacant");
+            };
+            assert_eq!(*entry.insert(value), value);
+        }
+        assert_eq!(trie.len(), 4);
+        for (key, value) in [
+            (&b"interstellar"[..], 1),
+            (b"inter", 2),
+            (b"intern", 3),
+            (b"i", 4),
+        ] {
+            assert_eq!(trie.get(key), Some(&value));
+        }
+        assert_eq!(trie.get(b"int"), None);
+        assert_eq!(trie.entry(b"inter").or_insert(0), &mut 2);
+    }
+}
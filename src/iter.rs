@@ -0,0 +1,1519 @@
+use std::borrow::Cow;
+use std::iter::FusedIterator;
+use std::ops::{Bound, Range as Slots};
+use std::vec;
+
+use crate::arena::{Arena, ArenaMut, NodeId, ROOT};
+use crate::children::ChildRange;
+use crate::digits::Digits;
+use crate::node::common_prefix_len;
+use crate::{Decoded, RadixNode, RadixTrie, BRANCH_FACTOR};
+
+/// The nodes of a trie as seen by a traversal: borrowed, mutably
+/// borrowed or owned. Splitting a node hands out its value and its
+/// children separately, which lets the mutable and owning iterators
+/// share the same walk. A traversal splits each node at most once,
+/// and only reads its label before splitting it.
+pub(crate) trait Nodes {
+    type Value;
+    type Children: DoubleEndedIterator<Item = (usize, NodeId)>;
+
+    /// the bytes along the edge into node past its selecting byte.
+    fn label(&self, node: NodeId) -> &[u8];
+
+    /// splits node into its value and the children
+    /// whose byte falls within slots.
+    fn split(&mut self, node: NodeId, slots: Slots<usize>)
+        -> (Option<Self::Value>, Self::Children);
+}
+
+impl<'a, T> Nodes for &'a Arena<T> {
+    type Value = &'a T;
+    type Children = ChildRange<'a>;
+
+    fn label(&self, node: NodeId) -> &[u8] {
+        &self[node].label
+    }
+
+    fn split(
+        &mut self,
+        node: NodeId,
+        slots: Slots<usize>,
+    ) -> (Option<Self::Value>, Self::Children) {
+        let arena: &'a Arena<T> = self;
+        let node = &arena[node];
+        let children = match node.children {
+            Some(ref children) => children.range(slots),
+            None => ChildRange::Empty,
+        };
+        (node.accept_state.as_ref(), children)
+    }
+}
+
+impl<'a, T> Nodes for ArenaMut<'a, T> {
+    type Value = &'a mut T;
+    type Children = ChildRange<'a>;
+
+    fn label(&self, node: NodeId) -> &[u8] {
+        // SAFETY: node has not been split yet, so none of it is borrowed.
+        unsafe { &self.node(node).label }
+    }
+
+    fn split(
+        &mut self,
+        node: NodeId,
+        slots: Slots<usize>,
+    ) -> (Option<Self::Value>, Self::Children) {
+        // SAFETY: each node is split at most once.
+        let RadixNode {
+            accept_state,
+            children,
+            ..
+        } = unsafe { self.node_mut(node) };
+        let children = match children {
+            Some(children) => children.range(slots),
+            None => ChildRange::Empty,
+        };
+        (accept_state.as_mut(), children)
+    }
+}
+
+impl<T> Nodes for Arena<T> {
+    type Value = T;
+    type Children = vec::IntoIter<(usize, NodeId)>;
+
+    fn label(&self, node: NodeId) -> &[u8] {
+        &self[node].label
+    }
+
+    fn split(
+        &mut self,
+        node: NodeId,
+        slots: Slots<usize>,
+    ) -> (Option<Self::Value>, Self::Children) {
+        let node = match node {
+            ROOT => std::mem::take(&mut self[ROOT]),
+            node => self.free(node),
+        };
+        let children: Vec<_> = match node.children {
+            Some(children) => children.range(slots).collect(),
+            None => Vec::new(),
+        };
+        (node.accept_state, children.into_iter())
+    }
+}
+
+/// A node whose value may not have been yielded yet and
+/// whose remaining children are still to be visited.
+struct Frame<N: Nodes> {
+    value: Option<N::Value>,
+    children: N::Children,
+    /// the length of this node's key.
+    depth: usize,
+}
+
+impl<N: Nodes> Frame<N> {
+    fn new(nodes: &mut N, node: NodeId, slots: Slots<usize>, depth: usize) -> Self {
+        let (value, children) = nodes.split(node, slots);
+        Self {
+            value,
+            children,
+            depth,
+        }
+    }
+}
+
+/// A depth-first walk of a subtree which can be consumed from both
+/// ends. Children are visited in order of their byte, so keys come
+/// out lexicographically from the front and in reverse from the back.
+///
+/// Each end keeps a stack of frames, innermost last. The outermost
+/// frame of the back stack is a child of some frame on the front
+/// stack, so both ends draw from the frames they share and nothing
+/// is yielded twice. Once one stack runs dry, that end carries on
+/// through the frames of the other, outermost first.
+pub(crate) struct Traversal<N: Nodes> {
+    nodes: N,
+    front: Vec<Frame<N>>,
+    /// holds the key of every frame on the front stack as a prefix.
+    front_key: Vec<u8>,
+    back: Vec<Frame<N>>,
+    /// holds the key of every frame on the back stack as a prefix.
+    back_key: Vec<u8>,
+}
+
+impl<N: Nodes> Traversal<N> {
+    /// starts a walk at node, whose key is prefix.
+    pub(crate) fn new(mut nodes: N, node: NodeId, prefix: Vec<u8>) -> Self {
+        let depth = prefix.len();
+        Self {
+            front: vec![Frame::new(&mut nodes, node, 0..BRANCH_FACTOR, depth)],
+            nodes,
+            front_key: prefix,
+            back: Vec::new(),
+            back_key: Vec::new(),
+        }
+    }
+
+    /// a walk which yields nothing.
+    pub(crate) fn empty(nodes: N) -> Self {
+        Self {
+            nodes,
+            front: Vec::new(),
+            front_key: Vec::new(),
+            back: Vec::new(),
+            back_key: Vec::new(),
+        }
+    }
+
+    /// starts a walk at the root which only visits the keys
+    /// between start and end. Subtrees outside of the bounds are
+    /// cut off as the walk descends along each bound, so they are
+    /// never visited.
+    pub(crate) fn range(nodes: N, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Self {
+        let mut traversal = Self::empty(nodes);
+        let mut node = ROOT;
+        let mut from = 0;
+
+        // While both bounds continue through the same child, the node is
+        // a proper prefix of both and nothing else beneath it is in range.
+        let (lo, hi) = loop {
+            let lo = position(&traversal.front_key, start, from);
+            let hi = position(&traversal.front_key, end, from);
+            match (lo, hi) {
+                (Some(Position::Below), _) | (_, Some(Position::Above)) => {
+                    return Self::empty(traversal.nodes)
+                }
+                (Some(Position::Prefix(lo)), Some(Position::Prefix(hi))) if lo == hi => {
+                    let (_, mut children) =
+                        traversal.nodes.split(node, lo as usize..lo as usize + 1);
+                    let Some((byte, child)) = children.next() else {
+                        return Self::empty(traversal.nodes);
+                    };
+                    from = traversal.front_key.len();
+                    let label = traversal.nodes.label(child);
+                    extend_key(&mut traversal.front_key, from, byte, label);
+                    node = child;
+                }
+                positions => break positions,
+            }
+        };
+        traversal.back_key.clone_from(&traversal.front_key);
+
+        // This is where the bounds diverge, so each end descends
+        // along its own bound from here.
+        let depth = traversal.front_key.len();
+        let accept = match (lo, start) {
+            (Some(Position::Prefix(_)), _) => false,
+            (Some(Position::Equal), bound) => matches!(bound, Bound::Included(_)),
+            _ => true,
+        } && match (hi, end) {
+            (Some(Position::Equal), bound) => matches!(bound, Bound::Included(_)),
+            _ => true,
+        };
+        let lower = match lo {
+            Some(Position::Prefix(lo)) => lo as usize,
+            _ => 0,
+        };
+        let upper = match hi {
+            Some(Position::Prefix(hi)) => hi as usize + 1,
+            // Every child of the end bound itself lies past it.
+            Some(Position::Equal) => 0,
+            _ => BRANCH_FACTOR,
+        };
+        let mut frame = Frame::new(&mut traversal.nodes, node, lower..upper.max(lower), depth);
+        if !accept {
+            frame.value = None;
+        }
+
+        // The children the bounds pass through are only partly in range.
+        // If start's child is missing, the first child found may be the
+        // one end passes through instead, and then it belongs to the back.
+        let mut first = match lo {
+            Some(Position::Prefix(_)) => frame.children.next(),
+            _ => None,
+        };
+        let last = match hi {
+            Some(Position::Prefix(hi))
+                if first.as_ref().is_some_and(|&(byte, _)| byte == hi as usize) =>
+            {
+                first.take()
+            }
+            Some(Position::Prefix(_)) => frame.children.next_back(),
+            _ => None,
+        };
+        traversal.front.push(frame);
+        if let Some((byte, child)) = first {
+            traversal.seek_front(child, byte, depth, start);
+        }
+        if let Some((byte, child)) = last {
+            traversal.seek_back(child, byte, depth, end);
+        }
+        traversal
+    }
+
+    /// pushes the child reached through byte from a node at depth
+    /// onto the front stack, descending along start for as long as
+    /// the child's key is a prefix of it.
+    fn seek_front(
+        &mut self,
+        mut child: NodeId,
+        mut byte: usize,
+        mut depth: usize,
+        start: Bound<&[u8]>,
+    ) {
+        loop {
+            let from = depth;
+            extend_key(&mut self.front_key, from, byte, self.nodes.label(child));
+            depth = self.front_key.len();
+            let slots = match position(&self.front_key, start, from) {
+                Some(Position::Below) => return,
+                Some(Position::Prefix(lo)) => lo as usize..BRANCH_FACTOR,
+                Some(Position::Equal) => {
+                    let mut frame = Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth);
+                    if !matches!(start, Bound::Included(_)) {
+                        frame.value = None;
+                    }
+                    self.front.push(frame);
+                    return;
+                }
+                // Everything beneath a key past the bound is in range.
+                Some(Position::Above) | None => {
+                    self.front
+                        .push(Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth));
+                    return;
+                }
+            };
+
+            let mut frame = Frame::new(&mut self.nodes, child, slots, depth);
+            frame.value = None;
+            let next = frame.children.next();
+            self.front.push(frame);
+            match next {
+                Some(next) => (byte, child) = next,
+                None => return,
+            }
+        }
+    }
+
+    /// pushes the child reached through byte from a node at depth
+    /// onto the back stack, descending along end for as long as
+    /// the child's key is a prefix of it.
+    fn seek_back(
+        &mut self,
+        mut child: NodeId,
+        mut byte: usize,
+        mut depth: usize,
+        end: Bound<&[u8]>,
+    ) {
+        loop {
+            let from = depth;
+            extend_key(&mut self.back_key, from, byte, self.nodes.label(child));
+            depth = self.back_key.len();
+            let slots = match position(&self.back_key, end, from) {
+                Some(Position::Above) => return,
+                Some(Position::Prefix(hi)) => 0..hi as usize + 1,
+                Some(Position::Equal) => {
+                    // Every child of the bound itself lies past it.
+                    let mut frame = Frame::new(&mut self.nodes, child, 0..0, depth);
+                    if !matches!(end, Bound::Included(_)) {
+                        frame.value = None;
+                    }
+                    self.back.push(frame);
+                    return;
+                }
+                // Everything beneath a key before the bound is in range.
+                Some(Position::Below) | None => {
+                    self.back
+                        .push(Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth));
+                    return;
+                }
+            };
+
+            let mut frame = Frame::new(&mut self.nodes, child, slots, depth);
+            let next = frame.children.next_back();
+            self.back.push(frame);
+            match next {
+                Some(next) => (byte, child) = next,
+                None => return,
+            }
+        }
+    }
+
+    /// returns the next value along with its key.
+    pub(crate) fn next(&mut self) -> Option<(&[u8], N::Value)> {
+        loop {
+            if let Some(top) = self.front.last_mut() {
+                if let Some(value) = top.value.take() {
+                    self.front_key.truncate(top.depth);
+                    return Some((&self.front_key, value));
+                }
+                match top.children.next() {
+                    Some((byte, child)) => {
+                        extend_key(
+                            &mut self.front_key,
+                            top.depth,
+                            byte,
+                            self.nodes.label(child),
+                        );
+                        let depth = self.front_key.len();
+                        self.front.push(Frame::new(
+                            &mut self.nodes,
+                            child,
+                            0..BRANCH_FACTOR,
+                            depth,
+                        ));
+                    }
+                    None => {
+                        self.front.pop();
+                    }
+                }
+                continue;
+            }
+
+            // The front stack is exhausted, so whatever is left hangs
+            // off the back stack, where outer frames come first.
+            let mut next = None;
+            for frame in self.back.iter_mut() {
+                if let Some(value) = frame.value.take() {
+                    return Some((&self.back_key[..frame.depth], value));
+                }
+                if let Some(child) = frame.children.next() {
+                    next = Some((frame.depth, child));
+                    break;
+                }
+            }
+            let (depth, (byte, child)) = next?;
+            self.front_key.clear();
+            self.front_key.extend_from_slice(&self.back_key[..depth]);
+            extend_key(&mut self.front_key, depth, byte, self.nodes.label(child));
+            let depth = self.front_key.len();
+            self.front
+                .push(Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth));
+        }
+    }
+
+    /// returns the last value along with its key.
+    pub(crate) fn next_back(&mut self) -> Option<(&[u8], N::Value)> {
+        loop {
+            if let Some(top) = self.back.last_mut() {
+                match top.children.next_back() {
+                    Some((byte, child)) => {
+                        extend_key(&mut self.back_key, top.depth, byte, self.nodes.label(child));
+                        let depth = self.back_key.len();
+                        self.back
+                            .push(Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth));
+                    }
+                    None => {
+                        // A node's value comes before all of its children.
+                        let depth = top.depth;
+                        if let Some(value) = self.back.pop().and_then(|frame| frame.value) {
+                            self.back_key.truncate(depth);
+                            return Some((&self.back_key, value));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // The back stack is exhausted, so whatever is left is held
+            // by the front stack, where outer frames come last.
+            let mut next = None;
+            for frame in self.front.iter_mut() {
+                if let Some(child) = frame.children.next_back() {
+                    next = Some((frame.depth, child));
+                    break;
+                }
+                if let Some(value) = frame.value.take() {
+                    return Some((&self.front_key[..frame.depth], value));
+                }
+            }
+            let (depth, (byte, child)) = next?;
+            self.back_key.clear();
+            self.back_key.extend_from_slice(&self.front_key[..depth]);
+            extend_key(&mut self.back_key, depth, byte, self.nodes.label(child));
+            let depth = self.back_key.len();
+            self.back
+                .push(Frame::new(&mut self.nodes, child, 0..BRANCH_FACTOR, depth));
+        }
+    }
+}
+
+/// sets key to the key of a child with this label, which is reached
+/// through byte from a node whose key is the first depth bytes of key.
+fn extend_key(key: &mut Vec<u8>, depth: usize, byte: usize, label: &[u8]) {
+    key.truncate(depth);
+    key.push(byte as u8);
+    key.extend_from_slice(label);
+}
+
+/// Where the keys beneath a node lie relative to a range bound.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// the node's key and everything beneath it sort before the bound.
+    Below,
+    /// the node's key is a proper prefix of the bound, which
+    /// continues through the child selected by this byte.
+    Prefix(u8),
+    /// the node's key is the bound.
+    Equal,
+    /// the node's key and everything beneath it sort after the bound.
+    Above,
+}
+
+/// locates key relative to the key a bound is set on, given that their
+/// first from bytes are known to match. Returns None if unbounded.
+fn position(key: &[u8], bound: Bound<&[u8]>, from: usize) -> Option<Position> {
+    let bound = match bound {
+        Bound::Included(bound) | Bound::Excluded(bound) => bound,
+        Bound::Unbounded => return None,
+    };
+    let common = from + common_prefix_len(&key[from..], bound.get(from..).unwrap_or_default());
+    let position = match (key.get(common), bound.get(common)) {
+        (None, None) => Position::Equal,
+        (None, Some(&byte)) => Position::Prefix(byte),
+        (Some(_), None) => Position::Above,
+        (Some(key), Some(bound)) if key < bound => Position::Below,
+        (Some(_), Some(_)) => Position::Above,
+    };
+    Some(position)
+}
+
+/// borrows the key a bound is set on.
+fn bound_ref<'b>(bound: &'b Bound<Cow<'_, [u8]>>) -> Bound<&'b [u8]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// An iterator over the entries of a trie in key order.
+/// It can also be consumed from the back.
+pub struct Iter<'a, T> {
+    inner: Traversal<&'a Arena<T>>,
+    remaining: usize,
+    digits: Digits,
+}
+
+/// A mutable iterator over the entries of a trie in key order.
+pub struct IterMut<'a, T> {
+    inner: Traversal<ArenaMut<'a, T>>,
+    remaining: usize,
+    digits: Digits,
+}
+
+/// An owning iterator over the entries of a trie in key order.
+pub struct IntoIter<T> {
+    inner: Traversal<Arena<T>>,
+    remaining: usize,
+    digits: Digits,
+}
+
+/// The mutable iterators hold raw pointers into the arena, but are
+/// Send and Sync whenever T is, as those of BTreeMap are.
+const _: fn() = || {
+    fn send_sync<X: Send + Sync>() {}
+    send_sync::<IterMut<'_, u32>>();
+    send_sync::<ValuesMut<'_, u32>>();
+    send_sync::<Decoded<IterMut<'_, u32>, u32>>();
+};
+
+/// An iterator over the keys of a trie in order.
+pub struct Keys<'a, T> {
+    inner: Iter<'a, T>,
+}
+
+/// An iterator over the values of a trie in key order.
+pub struct Values<'a, T> {
+    inner: Iter<'a, T>,
+}
+
+/// A mutable iterator over the values of a trie in key order.
+pub struct ValuesMut<'a, T> {
+    inner: IterMut<'a, T>,
+}
+
+/// An iterator over the entries whose keys start with a given
+/// prefix, in key order.
+pub struct Prefix<'a, T> {
+    inner: Traversal<&'a Arena<T>>,
+    digits: Digits,
+}
+
+/// An iterator over the keys which start with a given prefix, in order.
+pub struct PrefixKeys<'a, T> {
+    inner: Prefix<'a, T>,
+}
+
+/// An iterator over the entries whose keys fall within a range,
+/// in key order. It can also be consumed from the back.
+pub struct Range<'a, T> {
+    inner: Traversal<&'a Arena<T>>,
+    digits: Digits,
+}
+
+/// An iterator over the stored keys which are prefixes of a given
+/// key, shortest first. Keys are slices of the key searched for.
+pub struct CommonPrefixes<'a, 'k, T> {
+    arena: &'a Arena<T>,
+    /// the next node along the key's path, if any.
+    node: Option<NodeId>,
+    key: &'k [u8],
+    /// the digits of key, which the trie branches on.
+    path: Cow<'k, [u8]>,
+    /// the number of digits of key consumed to reach node.
+    depth: usize,
+    digits: Digits,
+}
+
+impl<'a, T> Iter<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>) -> Self {
+        Self {
+            inner: Traversal::new(&trie.arena, ROOT, Vec::new()),
+            remaining: trie.len,
+            digits: RadixTrie::<T, BITS>::DIGITS,
+        }
+    }
+
+    /// returns the next value without materializing its key.
+    fn next_value(&mut self) -> Option<&'a T> {
+        let (_, value) = self.inner.next()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn next_value_back(&mut self) -> Option<&'a T> {
+        let (_, value) = self.inner.next_back()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+}
+
+impl<'a, T> IterMut<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a mut RadixTrie<T, BITS>) -> Self {
+        Self {
+            inner: Traversal::new(ArenaMut::new(&mut trie.arena), ROOT, Vec::new()),
+            remaining: trie.len,
+            digits: RadixTrie::<T, BITS>::DIGITS,
+        }
+    }
+
+    fn next_value(&mut self) -> Option<&'a mut T> {
+        let (_, value) = self.inner.next()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn next_value_back(&mut self) -> Option<&'a mut T> {
+        let (_, value) = self.inner.next_back()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+}
+
+impl<'a, T> Keys<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>) -> Self {
+        Self {
+            inner: Iter::new(trie),
+        }
+    }
+}
+
+impl<'a, T> Values<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>) -> Self {
+        Self {
+            inner: Iter::new(trie),
+        }
+    }
+}
+
+impl<'a, T> ValuesMut<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a mut RadixTrie<T, BITS>) -> Self {
+        Self {
+            inner: IterMut::new(trie),
+        }
+    }
+}
+
+impl<'a, T> Prefix<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>, prefix: &[u8]) -> Self {
+        let digits = RadixTrie::<T, BITS>::DIGITS;
+        let prefix = digits.split(prefix);
+        let arena = &trie.arena;
+        let inner = match arena.find_prefix(&prefix) {
+            Some((node, rest)) => Traversal::new(arena, node, [&prefix, rest].concat()),
+            None => Traversal::empty(arena),
+        };
+        Self { inner, digits }
+    }
+}
+
+impl<'a, T> PrefixKeys<'a, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>, prefix: &[u8]) -> Self {
+        Self {
+            inner: Prefix::new(trie, prefix),
+        }
+    }
+}
+
+impl<'a, T> Range<'a, T> {
+    pub(crate) fn new<const BITS: usize>(
+        trie: &'a RadixTrie<T, BITS>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Self {
+        match (start, end) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+                panic!("range start and end are equal and excluded in RadixTrie")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if start > end => panic!("range start is greater than range end in RadixTrie"),
+            _ => {}
+        }
+        let digits = RadixTrie::<T, BITS>::DIGITS;
+        let start = start.map(|start| digits.split(start));
+        let end = end.map(|end| digits.split(end));
+        Self {
+            inner: Traversal::range(&trie.arena, bound_ref(&start), bound_ref(&end)),
+            digits,
+        }
+    }
+}
+
+impl<'a, 'k, T> CommonPrefixes<'a, 'k, T> {
+    pub(crate) fn new<const BITS: usize>(trie: &'a RadixTrie<T, BITS>, key: &'k [u8]) -> Self {
+        let digits = RadixTrie::<T, BITS>::DIGITS;
+        Self {
+            arena: &trie.arena,
+            node: Some(ROOT),
+            key,
+            path: digits.split(key),
+            depth: 0,
+            digits,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (Vec<u8>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = (Vec<u8>, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (Vec<u8>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> Iterator for Keys<'_, T> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Iterator for Values<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_value()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Iterator for ValuesMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_value()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Iterator for Prefix<'a, T> {
+    type Item = (Vec<u8>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> Iterator for PrefixKeys<'_, T> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = (Vec<u8>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> DoubleEndedIterator for IterMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        self.remaining -= 1;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> DoubleEndedIterator for Keys<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<T> DoubleEndedIterator for Values<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_value_back()
+    }
+}
+
+impl<T> DoubleEndedIterator for ValuesMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_value_back()
+    }
+}
+
+impl<T> DoubleEndedIterator for Prefix<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<T> DoubleEndedIterator for PrefixKeys<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<T> DoubleEndedIterator for Range<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        Some((self.digits.join(key), value))
+    }
+}
+
+impl<'a, 'k, T> Iterator for CommonPrefixes<'a, 'k, T> {
+    type Item = (&'k [u8], &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.node {
+            let node = &self.arena[node];
+            let depth = self.depth;
+            self.node = None;
+            if let Some((&byte, tail)) = self.path[depth..].split_first() {
+                if let Some(child) = node.child(byte) {
+                    let label = &self.arena[child].label;
+                    if tail.starts_with(label) {
+                        self.node = Some(child);
+                        self.depth = depth + 1 + label.len();
+                    }
+                }
+            }
+            if let Some(value) = node.accept_state.as_ref() {
+                return Some((&self.key[..self.digits.bytes(depth)], value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.node {
+            Some(_) => (0, Some(self.path.len() - self.depth + 1)),
+            None => (0, Some(0)),
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> ExactSizeIterator for Keys<'_, T> {}
+impl<T> ExactSizeIterator for Values<'_, T> {}
+impl<T> ExactSizeIterator for ValuesMut<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+impl<T> FusedIterator for IterMut<'_, T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+impl<T> FusedIterator for Keys<'_, T> {}
+impl<T> FusedIterator for Values<'_, T> {}
+impl<T> FusedIterator for ValuesMut<'_, T> {}
+impl<T> FusedIterator for Prefix<'_, T> {}
+impl<T> FusedIterator for PrefixKeys<'_, T> {}
+impl<T> FusedIterator for Range<'_, T> {}
+impl<T> FusedIterator for CommonPrefixes<'_, '_, T> {}
+
+impl<T, const BITS: usize> IntoIterator for RadixTrie<T, BITS> {
+    type Item = (Vec<u8>, T);
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            remaining: self.len,
+            inner: Traversal::new(self.arena, ROOT, Vec::new()),
+            digits: Self::DIGITS,
+        }
+    }
+}
+
+impl<'a, T, const BITS: usize> IntoIterator for &'a RadixTrie<T, BITS> {
+    type Item = (Vec<u8>, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const BITS: usize> IntoIterator for &'a mut RadixTrie<T, BITS> {
+    type Item = (Vec<u8>, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+    use crate::{BitTrie, Layout, RadixTrie};
+
+    const KEYS: &[&[u8]] = &[
+        b"",
+        b"\0",
+        b"\0\0",
+        b"\x01",
+        b"a",
+        b"ab",
+        b"abc",
+        b"abd",
+        b"b",
+        b"\x7f",
+        b"\x80",
+        b"\xff",
+        b"\xff\xff",
+    ];
+
+    fn sample() -> (RadixTrie<usize>, BTreeMap<Vec<u8>, usize>) {
+        let mut trie = RadixTrie::new();
+        let mut map = BTreeMap::new();
+        // insert in reverse so iteration order cannot follow insertion order
+        for (i, key) in KEYS.iter().enumerate().rev() {
+            trie.insert(key.to_vec(), i);
+            map.insert(key.to_vec(), i);
+        }
+        (trie, map)
+    }
+
+    #[test]
+    fn iter_visits_keys_in_order() {
+        let (trie, map) = sample();
+        let entries: Vec<_> = trie.iter().map(|(k, v)| (k.to_vec(), *v)).collect();
+        let expected: Vec<_> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        assert_eq!(entries, expected);
+        let keys: Vec<_> = trie.keys().map(|k| k.to_vec()).collect();
+        assert_eq!(keys, map.keys().cloned().collect::<Vec<_>>());
+        let values: Vec<_> = trie.values().copied().collect();
+        assert_eq!(values, map.values().copied().collect::<Vec<_>>());
+        assert_eq!((&trie).into_iter().count(), KEYS.len());
+    }
+
+    #[test]
+    fn iter_mut_and_values_mut_change_every_value() {
+        let (mut trie, map) = sample();
+        for (_, v) in trie.iter_mut() {
+            *v += 100;
+        }
+        for v in trie.values_mut() {
+            *v *= 2;
+        }
+        for (_, v) in &mut trie {
+            *v += 1;
+        }
+        for (key, value) in &map {
+            assert_eq!(trie.get(key), Some(&((value + 100) * 2 + 1)));
+        }
+    }
+
+    #[test]
+    fn into_iter_yields_owned_entries_in_order() {
+        let (trie, map) = sample();
+        let entries: Vec<_> = trie.into_iter().map(|(k, v)| (k.to_vec(), v)).collect();
+        assert_eq!(entries, map.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn iterators_report_exact_lengths() {
+        let (mut trie, _) = sample();
+        let n = KEYS.len();
+        let mut iter = trie.iter();
+        assert_eq!(iter.len(), n);
+        iter.next();
+        iter.next();
+        assert_eq!(iter.len(), n - 2);
+        assert_eq!(iter.by_ref().count(), n - 2);
+        assert_eq!(iter.len(), 0);
+        assert!(iter.next().is_none());
+
+        assert_eq!(trie.keys().len(), n);
+        assert_eq!(trie.values().len(), n);
+        assert_eq!(trie.iter_mut().len(), n);
+        assert_eq!(trie.values_mut().skip(3).len(), n - 3);
+        let mut into_iter = trie.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), n - 1);
+    }
+
+    #[test]
+    fn empty_trie_yields_nothing() {
+        let mut trie = RadixTrie::<u32>::new();
+        assert_eq!(trie.iter().len(), 0);
+        assert!(trie.iter().next().is_none());
+        assert!(trie.iter_mut().next().is_none());
+        assert!(trie.into_iter().next().is_none());
+
+        // a trie emptied by removal has no leftover entries either
+        let (mut trie, _) = sample();
+        for key in KEYS {
+            trie.remove(key);
+        }
+        assert!(trie.iter().next().is_none());
+    }
+
+    fn with_prefix(map: &BTreeMap<Vec<u8>, usize>, prefix: &[u8]) -> Vec<(Vec<u8>, usize)> {
+        map.iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.clone(), *v))
+            .collect()
+    }
+
+    #[test]
+    fn iter_prefix_matches_a_filtered_map() {
+        let (trie, map) = sample();
+        // prefixes which are keys, lie between keys, or match nothing
+        for prefix in [
+            &b""[..],
+            b"a",
+            b"ab",
+            b"abc",
+            b"abcd",
+            b"\0",
+            b"\xff",
+            b"c",
+            b"\x80\0",
+        ] {
+            let expected = with_prefix(&map, prefix);
+            let entries: Vec<_> = trie
+                .iter_prefix(prefix)
+                .map(|(k, v)| (k.to_vec(), *v))
+                .collect();
+            assert_eq!(entries, expected, "{prefix:?}");
+            let keys: Vec<_> = trie.keys_with_prefix(prefix).map(|k| k.to_vec()).collect();
+            let expected_keys: Vec<_> = expected.iter().map(|(k, _)| k.clone()).collect();
+            assert_eq!(keys, expected_keys, "{prefix:?}");
+            assert_eq!(trie.has_prefix(prefix), !expected.is_empty(), "{prefix:?}");
+        }
+    }
+
+    #[test]
+    fn prefix_ending_inside_a_long_key() {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"interstellar".to_vec(), 1);
+        trie.insert(b"internet".to_vec(), 2);
+        trie.insert(b"in".to_vec(), 3);
+        let keys: Vec<_> = trie
+            .keys_with_prefix(b"inter")
+            .map(|k| k.to_vec())
+            .collect();
+        assert_eq!(keys, [b"internet".to_vec(), b"interstellar".to_vec()]);
+        let keys: Vec<_> = trie
+            .keys_with_prefix(b"inters")
+            .map(|k| k.to_vec())
+            .collect();
+        assert_eq!(keys, [b"interstellar".to_vec()]);
+        assert!(trie.has_prefix(b"interst"));
+        assert!(!trie.has_prefix(b"interx"));
+        assert!(!trie.has_prefix(b"interstellars"));
+        assert_eq!(trie.iter_prefix(b"interx").count(), 0);
+
+        // a prefix left behind by a removed key matches nothing
+        trie.remove(b"interstellar");
+        assert!(!trie.has_prefix(b"inters"));
+        assert_eq!(trie.iter_prefix(b"inters").count(), 0);
+    }
+
+    #[test]
+    fn common_prefix_search_yields_stored_prefixes_shortest_first() {
+        let (trie, _) = sample();
+        let found: Vec<_> = trie.common_prefix_search(b"abcd").collect();
+        assert_eq!(
+            found,
+            [
+                (&b""[..], &0),
+                (&b"a"[..], &4),
+                (&b"ab"[..], &5),
+                (&b"abc"[..], &6)
+            ]
+        );
+        let found: Vec<_> = trie
+            .common_prefix_search(b"\0\0\0")
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(found, [&b""[..], b"\0", b"\0\0"]);
+        let found: Vec<_> = trie.common_prefix_search(b"c").map(|(k, _)| k).collect();
+        assert_eq!(found, [&b""[..]]);
+    }
+
+    #[test]
+    fn longest_prefix_match_picks_the_longest_stored_prefix() {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"10.0".to_vec(), 1);
+        trie.insert(b"10.0.0".to_vec(), 2);
+        trie.insert(b"10.0.0.1".to_vec(), 3);
+        assert_eq!(
+            trie.longest_prefix_match(b"10.0.0.1"),
+            Some((&b"10.0.0.1"[..], &3))
+        );
+        assert_eq!(
+            trie.longest_prefix_match(b"10.0.0.2"),
+            Some((&b"10.0.0"[..], &2))
+        );
+        // the search may end partway along a longer key
+        assert_eq!(
+            trie.longest_prefix_match(b"10.0.1"),
+            Some((&b"10.0"[..], &1))
+        );
+        assert_eq!(
+            trie.longest_prefix_match(b"10.0.0."),
+            Some((&b"10.0.0"[..], &2))
+        );
+        assert_eq!(trie.longest_prefix_match(b"10."), None);
+        assert_eq!(trie.longest_prefix_match(b"11"), None);
+        assert_eq!(trie.longest_prefix_match(b""), None);
+        trie.insert(b"".to_vec(), 0);
+        assert_eq!(trie.longest_prefix_match(b"11"), Some((&b""[..], &0)));
+    }
+
+    /// keys which branch at the root, partway along longer keys
+    /// and at their ends.
+    const RANGE_KEYS: &[&[u8]] = &[
+        b"",
+        b"a",
+        b"apple",
+        b"applesauce",
+        b"application",
+        b"apply",
+        b"apt",
+        b"b",
+        b"banana",
+        b"band",
+        b"bandana",
+        b"bandit",
+        b"c\0",
+        b"zz",
+        &[0xff],
+        &[0xff, 0xff, 0x00],
+    ];
+
+    /// bounds which are not keys: prefixes of keys, keys which part
+    /// from a longer key partway along it or run on past it, and keys
+    /// before or after every key of a subtree.
+    const MISSING: &[&[u8]] = &[
+        b"ap",
+        b"appl",
+        b"appli",
+        b"applicatio",
+        b"applications",
+        b"applez",
+        b"apq",
+        b"ba",
+        b"bana",
+        b"bandb",
+        b"bb",
+        b"c",
+        b"d",
+        &[0x00],
+        &[0xfe],
+        &[0xff, 0xff],
+        &[0xff, 0xff, 0x00, 0x01],
+    ];
+
+    type Bounds<'k> = (Bound<&'k [u8]>, Bound<&'k [u8]>);
+
+    fn tries<const BITS: usize>() -> (Vec<RadixTrie<u32, BITS>>, BTreeMap<Vec<u8>, u32>) {
+        let map: BTreeMap<_, _> = (0..)
+            .zip(RANGE_KEYS)
+            .map(|(i, key)| (key.to_vec(), i))
+            .collect();
+        let tries = [Layout::Adaptive, Layout::Bitmap].map(|layout| {
+            let mut trie = RadixTrie::with_layout(layout);
+            for (key, &value) in &map {
+                trie.insert(key.clone(), value);
+            }
+            trie
+        });
+        (tries.into(), map)
+    }
+
+    /// checks that range yields what BTreeMap::range does, from the
+    /// front, from the back and from both ends in turn.
+    fn check<const BITS: usize>(
+        trie: &RadixTrie<u32, BITS>,
+        map: &BTreeMap<Vec<u8>, u32>,
+        bounds: Bounds<'_>,
+    ) {
+        let expected: Vec<_> = map
+            .range::<[u8], _>(bounds)
+            .map(|(k, &v)| (k.clone(), v))
+            .collect();
+        let forward: Vec<_> = trie.range(bounds).map(|(k, &v)| (k, v)).collect();
+        assert_eq!(forward, expected, "forward over {bounds:?}");
+        let mut backward: Vec<_> = trie.range(bounds).rev().map(|(k, &v)| (k, v)).collect();
+        backward.reverse();
+        assert_eq!(backward, expected, "backward over {bounds:?}");
+
+        let mut range = trie.range(bounds);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some((k, &v)) = range.next() {
+            front.push((k, v));
+            let Some((k, &v)) = range.next_back() else {
+                break;
+            };
+            back.push((k, v));
+        }
+        assert!(range.next().is_none() && range.next_back().is_none());
+        front.extend(back.into_iter().rev());
+        assert_eq!(front, expected, "alternating over {bounds:?}");
+    }
+
+    /// every bound on a key or a missing key, of each kind.
+    fn bounds() -> Vec<Bound<&'static [u8]>> {
+        let keys = RANGE_KEYS.iter().chain(MISSING).copied();
+        let mut bounds: Vec<_> = keys
+            .flat_map(|key| [Included(key), Excluded(key)])
+            .collect();
+        bounds.push(Unbounded);
+        bounds
+    }
+
+    /// whether BTreeMap::range accepts the bounds rather than panicking.
+    fn valid(bounds: Bounds<'_>) -> bool {
+        match bounds {
+            (Excluded(start), Excluded(end)) if start == end => false,
+            (Included(start) | Excluded(start), Included(end) | Excluded(end)) => start <= end,
+            _ => true,
+        }
+    }
+
+    fn check_all<const BITS: usize>() {
+        let (tries, map) = tries::<BITS>();
+        let bounds = bounds();
+        for trie in &tries {
+            for &start in &bounds {
+                for &end in &bounds {
+                    if valid((start, end)) {
+                        check(trie, &map, (start, end));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn range_matches_btreemap() {
+        check_all::<8>();
+    }
+
+    #[test]
+    fn range_matches_btreemap_with_narrow_digits() {
+        check_all::<1>();
+        check_all::<2>();
+        check_all::<4>();
+    }
+
+    fn range_keys(trie: &RadixTrie<u32>, bounds: Bounds<'_>) -> Vec<Vec<u8>> {
+        trie.range(bounds).map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn range_bounds_between_keys() {
+        let (tries, map) = tries::<8>();
+        for trie in &tries {
+            // neither "appl" nor "applicatio" is a key.
+            let bounds = (Included(&b"appl"[..]), Excluded(&b"applicatio"[..]));
+            assert_eq!(range_keys(trie, bounds), [&b"apple"[..], b"applesauce"]);
+            check(trie, &map, bounds);
+            let bounds = (Excluded(&b"ban"[..]), Included(&b"bandi"[..]));
+            assert_eq!(
+                range_keys(trie, bounds),
+                [&b"banana"[..], b"band", b"bandana"]
+            );
+            check(trie, &map, bounds);
+        }
+    }
+
+    #[test]
+    fn range_bounds_through_the_same_child() {
+        let (tries, map) = tries::<8>();
+        for trie in &tries {
+            // Both bounds pass through "a" and then "appl".
+            let bounds = (Excluded(&b"apple"[..]), Included(&b"application"[..]));
+            assert_eq!(
+                range_keys(trie, bounds),
+                [&b"applesauce"[..], b"application"]
+            );
+            check(trie, &map, bounds);
+            let bounds = (Included(&b"applesauce"[..]), Included(&b"applesauce"[..]));
+            assert_eq!(range_keys(trie, bounds), [&b"applesauce"[..]]);
+            let bounds = (Included(&b"band"[..]), Excluded(&b"band"[..]));
+            assert!(range_keys(trie, bounds).is_empty());
+        }
+    }
+
+    #[test]
+    fn range_from_both_ends() {
+        let (tries, _) = tries::<8>();
+        for trie in &tries {
+            let mut range = trie.range::<(Bound<&[u8]>, Bound<&[u8]>)>((Unbounded, Unbounded));
+            assert_eq!(range.next().unwrap().0, b"");
+            assert_eq!(range.next_back().unwrap().0, [0xff, 0xff, 0x00]);
+            assert_eq!(range.next_back().unwrap().0, [0xff]);
+            assert_eq!(range.next().unwrap().0, b"a");
+            let rest: Vec<_> = range.rev().map(|(key, _)| key).collect();
+            assert_eq!(rest.first().unwrap(), b"zz");
+            assert_eq!(rest.last().unwrap(), b"apple");
+            assert_eq!(rest.len(), RANGE_KEYS.len() - 4);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "range start is greater than range end in RadixTrie")]
+    fn range_start_after_end() {
+        let (tries, _) = tries::<8>();
+        tries[0].range::<(Bound<&[u8]>, Bound<&[u8]>)>((Included(b"b"), Included(b"a")));
+    }
+
+    #[test]
+    #[should_panic(expected = "range start and end are equal and excluded in RadixTrie")]
+    fn range_start_and_end_equal_and_excluded() {
+        let (tries, _) = tries::<8>();
+        tries[0].range::<(Bound<&[u8]>, Bound<&[u8]>)>((Excluded(b"a"), Excluded(b"a")));
+    }
+
+    #[test]
+    fn every_iterator_runs_in_reverse() {
+        let (mut trie, map) = sample();
+        let expected: Vec<_> = map.iter().rev().map(|(k, v)| (k.clone(), *v)).collect();
+        let entries: Vec<_> = trie.iter().rev().map(|(k, v)| (k.to_vec(), *v)).collect();
+        assert_eq!(entries, expected);
+        let keys: Vec<_> = trie.keys().rev().map(|k| k.to_vec()).collect();
+        assert_eq!(keys, map.keys().rev().cloned().collect::<Vec<_>>());
+        let values: Vec<_> = trie.values().rev().copied().collect();
+        assert_eq!(values, map.values().rev().copied().collect::<Vec<_>>());
+        let entries: Vec<_> = trie
+            .iter_mut()
+            .rev()
+            .map(|(k, v)| (k.to_vec(), *v))
+            .collect();
+        assert_eq!(entries, expected);
+        let values: Vec<_> = trie.values_mut().rev().map(|v| *v).collect();
+        assert_eq!(values, map.values().rev().copied().collect::<Vec<_>>());
+        let entries: Vec<_> = trie
+            .into_iter()
+            .rev()
+            .map(|(k, v)| (k.to_vec(), v))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn iter_prefix_runs_in_reverse() {
+        let (trie, map) = sample();
+        for prefix in [&b""[..], b"a", b"ab", b"abc", b"\0", b"c"] {
+            let mut expected = with_prefix(&map, prefix);
+            expected.reverse();
+            let entries: Vec<_> = trie
+                .iter_prefix(prefix)
+                .rev()
+                .map(|(k, v)| (k.to_vec(), *v))
+                .collect();
+            assert_eq!(entries, expected, "{prefix:?}");
+            let keys: Vec<_> = trie
+                .keys_with_prefix(prefix)
+                .rev()
+                .map(|k| k.to_vec())
+                .collect();
+            let expected_keys: Vec<_> = expected.into_iter().map(|(k, _)| k).collect();
+            assert_eq!(keys, expected_keys, "{prefix:?}");
+        }
+    }
+
+    #[test]
+    fn iterators_meet_in_the_middle() {
+        let (trie, _) = sample();
+        let mut iter = trie.iter();
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        loop {
+            let len = iter.len();
+            match iter.next() {
+                Some((k, _)) => front.push(k.to_vec()),
+                None => break,
+            }
+            assert_eq!(iter.len(), len - 1);
+            match iter.next_back() {
+                Some((k, _)) => back.push(k.to_vec()),
+                None => break,
+            }
+        }
+        assert!(iter.next_back().is_none());
+        front.extend(back.into_iter().rev());
+        let mut sorted: Vec<_> = KEYS.iter().map(|k| k.to_vec()).collect();
+        sorted.sort();
+        assert_eq!(front, sorted);
+
+        let mut prefix = trie.iter_prefix(b"ab");
+        assert_eq!(prefix.next_back().unwrap().0, b"abd");
+        assert_eq!(prefix.next().unwrap().0, b"ab");
+        assert_eq!(prefix.next_back().unwrap().0, b"abc");
+        assert!(prefix.next().is_none());
+        assert!(prefix.next_back().is_none());
+    }
+
+    /// pseudo-random keys over a few bytes, so that many of them share
+    /// prefixes or are prefixes of one another, in ascending order.
+    fn sample_keys() -> Vec<Vec<u8>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut keys = BTreeSet::from([Vec::new()]);
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let len = state as usize % 6;
+            let key = state.to_be_bytes()[2..2 + len]
+                .iter()
+                .map(|byte| [0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff][*byte as usize % 6])
+                .collect();
+            keys.insert(key);
+        }
+        keys.into_iter().collect()
+    }
+
+    /// drives iter_mut from both ends in turn, holding on to every
+    /// reference it yields, then writes through all of them. Each value
+    /// must be yielded exactly once, so each ends up incremented once.
+    fn iter_mut_from_both_ends<const BITS: usize>(mut trie: RadixTrie<u32, BITS>) {
+        let keys = sample_keys();
+        for key in &keys {
+            trie.insert(key.clone(), 0);
+        }
+
+        let mut iter = trie.iter_mut();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(entry) = iter.next() {
+            front.push(entry);
+            let Some(entry) = iter.next_back() else {
+                break;
+            };
+            back.push(entry);
+        }
+        assert!(iter.next().is_none() && iter.next_back().is_none());
+        front.extend(back.into_iter().rev());
+        for (_, value) in &mut front {
+            **value += 1;
+        }
+
+        let visited: Vec<_> = front.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(visited, keys);
+        assert!(trie.values().all(|&value| value == 1));
+    }
+
+    #[test]
+    fn iter_mut_visits_each_value_once() {
+        iter_mut_from_both_ends(RadixTrie::<_>::with_layout(Layout::Adaptive));
+    }
+
+    #[test]
+    fn iter_mut_visits_each_value_once_with_bitmap_layout() {
+        iter_mut_from_both_ends(RadixTrie::<_>::with_layout(Layout::Bitmap));
+    }
+
+    #[test]
+    fn iter_mut_visits_each_value_once_on_bit_trie() {
+        iter_mut_from_both_ends(BitTrie::with_layout(Layout::Adaptive));
+        iter_mut_from_both_ends(BitTrie::with_layout(Layout::Bitmap));
+    }
+}
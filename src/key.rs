@@ -0,0 +1,439 @@
+/// A type which can be stored as the key of a TypedRadixTrie. Keys are
+/// encoded into byte strings which sort in the same order as the keys
+/// themselves, so the trie iterates them in their natural order.
+///
+/// Every encoding is self-delimiting: no key's encoding is a proper
+/// prefix of another's, and the encoding of a tuple is the encodings
+/// of its fields one after another. All keys whose leading fields are
+/// the same therefore share the encoding of those fields as a prefix.
+pub trait TrieKey: Sized {
+    /// appends the encoding of the key to out.
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// reads a key from the front of input and advances past it,
+    /// returning None if input does not start with a valid encoding.
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+
+    /// returns the encoding of the key.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+/// A borrowed form of a key of type K, such as str for String, which
+/// is encoded the same way as the K it stands for. A TypedRadixTrie
+/// can be searched with one without building a K first.
+pub trait BorrowedKey<K: TrieKey> {
+    /// appends the encoding of the K this stands for to out.
+    fn encode_key(&self, out: &mut Vec<u8>);
+}
+
+impl<K: TrieKey> BorrowedKey<K> for K {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        self.encode(out);
+    }
+}
+
+/// takes the first N bytes of input.
+fn take<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, rest) = input.split_first_chunk::<N>()?;
+    *input = rest;
+    Some(*head)
+}
+
+/// Unsigned integers are stored big-endian, most significant byte first.
+macro_rules! unsigned {
+    ($($ty:ty),*) => {$(
+        impl TrieKey for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode(input: &mut &[u8]) -> Option<Self> {
+                take(input).map(<$ty>::from_be_bytes)
+            }
+        }
+    )*};
+}
+
+unsigned!(u8, u16, u32, u64, u128);
+
+/// Signed integers have their sign bit flipped, so that negative
+/// numbers come before positive ones, and are then stored as unsigned.
+macro_rules! signed {
+    ($($ty:ty => $unsigned:ty),*) => {$(
+        impl TrieKey for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                (*self as $unsigned ^ <$ty>::MIN as $unsigned).encode(out);
+            }
+
+            fn decode(input: &mut &[u8]) -> Option<Self> {
+                <$unsigned>::decode(input).map(|bits| (bits ^ <$ty>::MIN as $unsigned) as $ty)
+            }
+        }
+    )*};
+}
+
+signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+/// usize and isize are stored as 64 bits wide on every platform, so
+/// keys encoded on one can be decoded on another where they fit.
+impl TrieKey for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        u64::decode(input).and_then(|key| key.try_into().ok())
+    }
+}
+
+impl TrieKey for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        i64::decode(input).and_then(|key| key.try_into().ok())
+    }
+}
+
+/// Floats are stored in the order of total_cmp: positive numbers have
+/// their sign bit set, and negative ones have all their bits flipped,
+/// so that larger magnitudes come first. -0.0 sorts before 0.0, and
+/// NaNs sort at either end according to their sign.
+macro_rules! float {
+    ($($ty:ty => $bits:ty),*) => {$(
+        impl TrieKey for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                let bits = self.to_bits();
+                let sign = 1 << (<$bits>::BITS - 1);
+                let bits = if bits & sign == 0 { bits | sign } else { !bits };
+                bits.encode(out);
+            }
+
+            fn decode(input: &mut &[u8]) -> Option<Self> {
+                let bits = <$bits>::decode(input)?;
+                let sign = 1 << (<$bits>::BITS - 1);
+                let bits = if bits & sign == 0 { !bits } else { bits ^ sign };
+                Some(<$ty>::from_bits(bits))
+            }
+        }
+    )*};
+}
+
+float!(f32 => u32, f64 => u64);
+
+/// Byte strings of any length end in a pair of zero bytes, and any zero
+/// byte within them is followed by 0xff so it cannot be taken for the
+/// end. A string which is a prefix of another ends with a zero where
+/// the other continues, so it sorts first.
+impl TrieKey for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_bytes(self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let mut key = Vec::new();
+        loop {
+            match take(input)? {
+                [0] => match take(input)? {
+                    [0] => return Some(key),
+                    [0xff] => key.push(0),
+                    _ => return None,
+                },
+                [byte] => key.push(byte),
+            }
+        }
+    }
+}
+
+/// Strings are encoded as their UTF-8 bytes, which sort the
+/// same way as the strings do.
+impl TrieKey for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::decode(input)?).ok()
+    }
+}
+
+impl BorrowedKey<Vec<u8>> for [u8] {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_bytes(self, out);
+    }
+}
+
+impl BorrowedKey<String> for str {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), out);
+    }
+}
+
+/// appends bytes as a byte string, escaped and terminated.
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        out.push(byte);
+        if byte == 0 {
+            out.push(0xff);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+/// Byte arrays are all the same length, so they are stored as they are.
+impl<const N: usize> TrieKey for [u8; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        take(input)
+    }
+}
+
+/// None is stored as a single zero byte, and sorts before every Some,
+/// which is stored as a one byte followed by its value.
+impl<K: TrieKey> TrieKey for Option<K> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(key) => {
+                out.push(1);
+                key.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match take(input)? {
+            [0] => Some(None),
+            [1] => K::decode(input).map(Some),
+            _ => None,
+        }
+    }
+}
+
+/// Tuples are stored as their fields one after another, and so
+/// sort by their first field, then their second, and so on.
+macro_rules! tuple {
+    ($($name:ident)+) => {
+        impl<$($name: TrieKey),+> TrieKey for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode(out);)+
+            }
+
+            fn decode(input: &mut &[u8]) -> Option<Self> {
+                Some(($($name::decode(input)?,)+))
+            }
+        }
+    };
+}
+
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::{BorrowedKey, TrieKey};
+
+    /// checks that keys given in ascending order decode back to
+    /// themselves, leaving whatever follows them, and that their
+    /// encodings ascend and are none of them a prefix of another.
+    fn check<K: TrieKey + Debug>(ascending: &[K], same: impl Fn(&K, &K) -> bool) {
+        let encoded: Vec<_> = ascending.iter().map(K::to_bytes).collect();
+        for (key, bytes) in ascending.iter().zip(&encoded) {
+            let input = [bytes.as_slice(), b"\0rest"].concat();
+            let mut rest = input.as_slice();
+            let decoded = K::decode(&mut rest).expect("a key decodes");
+            assert!(same(&decoded, key), "{key:?} decoded as {decoded:?}");
+            assert_eq!(rest, b"\0rest", "{key:?} read past its end");
+        }
+        for (i, lower) in encoded.iter().enumerate() {
+            for (j, upper) in encoded.iter().enumerate().skip(i + 1) {
+                let (a, b) = (&ascending[i], &ascending[j]);
+                assert!(lower < upper, "{a:?} does not encode before {b:?}");
+                assert!(
+                    !upper.starts_with(lower),
+                    "{a:?} encodes as a prefix of {b:?}"
+                );
+            }
+        }
+    }
+
+    /// checks keys of a type with a total order, given in any order.
+    fn check_ord<K: TrieKey + Ord + Clone + Debug>(keys: &[K]) {
+        let mut ascending = keys.to_vec();
+        ascending.sort();
+        ascending.dedup();
+        check(&ascending, K::eq);
+    }
+
+    macro_rules! integers {
+        ($($ty:ty),*) => {$(
+            check_ord::<$ty>(&[
+                <$ty>::MIN,
+                <$ty>::MIN + 1,
+                <$ty>::MIN / 2,
+                0,
+                1,
+                <$ty>::MAX / 2,
+                <$ty>::MAX - 1,
+                <$ty>::MAX,
+                (0 as $ty).wrapping_sub(1),
+                0x7f as $ty,
+            ]);
+        )*};
+    }
+
+    #[test]
+    fn integers() {
+        integers!(u8, u16, u32, u64, u128, usize);
+        integers!(i8, i16, i32, i64, i128, isize);
+    }
+
+    macro_rules! floats {
+        ($($ty:ty),*) => {$(
+            let mut keys = vec![
+                <$ty>::NEG_INFINITY,
+                <$ty>::MIN,
+                -1.5,
+                -1.0,
+                -<$ty>::MIN_POSITIVE,
+                -<$ty>::from_bits(1),
+                -0.0,
+                0.0,
+                <$ty>::from_bits(1),
+                <$ty>::MIN_POSITIVE,
+                1.0,
+                1.5,
+                <$ty>::MAX,
+                <$ty>::INFINITY,
+                <$ty>::NAN,
+                -<$ty>::NAN,
+            ];
+            keys.sort_by(<$ty>::total_cmp);
+            check(&keys, |a, b| a.to_bits() == b.to_bits());
+            // -0.0 and 0.0 are distinct keys, as are NaNs of either sign.
+            assert!((-0.0 as $ty).to_bytes() < (0.0 as $ty).to_bytes());
+            assert!((-<$ty>::NAN).to_bytes() < <$ty>::NEG_INFINITY.to_bytes());
+            assert!(<$ty>::NAN.to_bytes() > <$ty>::INFINITY.to_bytes());
+        )*};
+    }
+
+    #[test]
+    fn floats() {
+        floats!(f32, f64);
+    }
+
+    #[test]
+    fn byte_strings() {
+        let keys: Vec<Vec<u8>> = [
+            &b""[..],
+            b"\0",
+            b"\0\0",
+            b"\0\x01",
+            b"\0\xff",
+            b"\x01",
+            b"a",
+            b"a\0",
+            b"a\0b",
+            b"ab",
+            b"\xfe",
+            b"\xff",
+            b"\xff\0",
+            b"\xff\xff",
+        ]
+        .map(<[u8]>::to_vec)
+        .into();
+        check_ord(&keys);
+        for key in &keys {
+            let mut borrowed = Vec::new();
+            BorrowedKey::<Vec<u8>>::encode_key(key.as_slice(), &mut borrowed);
+            assert_eq!(borrowed, key.to_bytes());
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let keys = [
+            "",
+            "\0",
+            "\0a",
+            "a",
+            "a\0",
+            "a\0\0",
+            "ab",
+            "é",
+            "\u{10ffff}",
+        ]
+        .map(String::from);
+        check_ord(&keys);
+        for key in &keys {
+            let mut borrowed = Vec::new();
+            BorrowedKey::<String>::encode_key(key.as_str(), &mut borrowed);
+            assert_eq!(borrowed, key.to_bytes());
+        }
+    }
+
+    #[test]
+    fn arrays_options_and_tuples() {
+        check_ord(&[[0u8; 3], [0, 0, 1], [0, 1, 0], [0xff; 3]]);
+        check_ord(&[[0u8; 0]]);
+        check_ord(&[None, Some(0u8), Some(1), Some(0xff)]);
+        check_ord(&[None, Some(None), Some(Some(i8::MIN)), Some(Some(i8::MAX))]);
+        check_ord(&[
+            None,
+            Some(String::new()),
+            Some("\0".into()),
+            Some("a".into()),
+        ]);
+        check_ord(&[
+            (0u8, String::new()),
+            (0, "\0".into()),
+            (0, "a".into()),
+            (1, String::new()),
+        ]);
+        check_ord(&[
+            (String::new(), 1u16),
+            ("a".into(), 0),
+            ("a".into(), 1),
+            ("a\0".into(), 0),
+            ("b".into(), 0),
+        ]);
+        check_ord(&[
+            (-1i32, None, 0u8),
+            (-1, Some(b"".to_vec()), 0),
+            (-1, Some(b"\0".to_vec()), 0),
+            (0, None, 0),
+            (0, None, 1),
+        ]);
+        check_ord(&[
+            (0u8, 0u8, String::new(), 0i64),
+            (0, 0, String::new(), i64::MAX),
+            (0, 0, "\0".into(), i64::MIN),
+            (0, 1, String::new(), 0),
+        ]);
+    }
+
+    #[test]
+    fn invalid_encodings_are_rejected() {
+        assert_eq!(u32::decode(&mut &[0, 0, 1][..]), None);
+        assert_eq!(Vec::<u8>::decode(&mut &b"a\0"[..]), None);
+        assert_eq!(Vec::<u8>::decode(&mut &b"a\0\x01\0\0"[..]), None);
+        assert_eq!(Vec::<u8>::decode(&mut &b"a"[..]), None);
+        assert_eq!(String::decode(&mut &b"\xff\0\0"[..]), None);
+        assert_eq!(Option::<u8>::decode(&mut &[2, 0][..]), None);
+        assert_eq!(Option::<u8>::decode(&mut &[1][..]), None);
+        assert_eq!(<(u8, u8)>::decode(&mut &[1][..]), None);
+    }
+}
@@ -1,126 +1,904 @@
-use std::mem::MaybeUninit;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Index, RangeBounds};
 
-const BRANCH_FACTOR: usize = 256;
+mod arena;
+mod builder;
+mod children;
+mod digits;
+mod entry;
+mod iter;
+mod key;
+mod node;
+mod persistent;
+mod snapshot;
+mod stats;
+mod typed;
 
-/// Each array contains a list of items.
-/// In our case, the items are nodes which point
-/// to the next level.
-type RadixArray<T> = [T; BRANCH_FACTOR];
-/// Each level is the child of another level, expect
-/// for the root.
-type Level<T> = RadixArray<RadixNode<T>>;
+pub use builder::TrieBuilder;
+pub use children::Layout;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use iter::{
+    CommonPrefixes, IntoIter, Iter, IterMut, Keys, Prefix, PrefixKeys, Range, Values, ValuesMut,
+};
+pub use key::{BorrowedKey, TrieKey};
+pub use persistent::{PersistentIter, PersistentRadixTrie};
+pub use snapshot::TrieSnapshot;
+pub use stats::Stats;
+pub use typed::{Decoded, TypedRadixTrie};
 
+use arena::Arena;
+use digits::Digits;
+use node::{RadixNode, BRANCH_FACTOR};
+
+/// A map from byte strings to values of type T, stored as a radix
+/// tree. Keys are split into digits of BITS bits each, which must be
+/// 1, 2, 4 or 8, and every node branches on one digit. Narrower digits
+/// mean smaller nodes but deeper paths. The default of 8 branches on
+/// whole bytes; see NibbleTrie and BitTrie for the others in common use.
 #[allow(dead_code)]
-pub struct RadixTrie<T> {
-    root: RadixNode<T>,
-    node_count: usize,
+#[derive(Clone)]
+pub struct RadixTrie<T, const BITS: usize = 8> {
+    /// holds every node of the trie, starting with the root.
+    arena: Arena<T>,
+    /// the number of distinct keys stored in the trie.
+    len: usize,
 }
 
+/// A trie which branches on 4-bit digits, 16 ways per node.
+/// new() only exists for byte tries, so build one with
+/// `NibbleTrie::default()` or `NibbleTrie::with_layout(layout)`.
+pub type NibbleTrie<T> = RadixTrie<T, 4>;
+
+/// A trie which branches on single bits, 2 ways per node,
+/// as in the routing tables of IP routers. Like NibbleTrie, it
+/// is built with `BitTrie::default()` or `BitTrie::with_layout(layout)`.
+pub type BitTrie<T> = RadixTrie<T, 1>;
+
 impl<T> RadixTrie<T> {
+    /// returns an empty byte trie. Tries of other digit widths are
+    /// built with default() or with_layout instead, as new() would
+    /// leave BITS to be inferred.
     pub fn new() -> Self {
+        Self::with_layout(Layout::default())
+    }
+}
+
+impl<T, const BITS: usize> RadixTrie<T, BITS> {
+    pub(crate) const DIGITS: Digits = Digits::new(BITS);
+
+    /// the most children a node of the trie can have.
+    pub const BRANCH_FACTOR: usize = Self::DIGITS.radix();
+
+    /// returns an empty trie whose nodes store their children in layout.
+    /// The digit width is not inferred, so name it or let it default,
+    /// as in `RadixTrie::<_>::with_layout(Layout::Bitmap)`.
+    pub fn with_layout(layout: Layout) -> Self {
         Self {
-            root: RadixNode::default(),
-            node_count: 0,
+            arena: Arena::new(layout),
+            len: 0,
+        }
+    }
+
+    /// returns a trie holding the entries of iter, built in one pass
+    /// with a TrieBuilder. Keys should come in ascending order; any
+    /// which do not are still stored, only more slowly. A key which
+    /// appears twice keeps the later value.
+    pub fn from_sorted_iter<I, K>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, T)>,
+        K: AsRef<[u8]>,
+    {
+        let mut builder = TrieBuilder::default();
+        for (key, value) in iter {
+            builder.push(key, value);
         }
+        builder.build()
     }
 
+    /// returns how the nodes of the trie store their children.
+    pub fn layout(&self) -> Layout {
+        self.arena.layout
+    }
+
+    /// returns the number of distinct keys stored in the trie.
     pub fn len(&self) -> usize {
-        self.node_count
+        self.len
+    }
+
+    /// returns the number of radix nodes currently allocated,
+    /// including the root.
+    pub fn node_count(&self) -> usize {
+        self.arena.count()
     }
 
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: T) {
+    /// returns a reference to the value stored under key, if any.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&T> {
+        let digits = Self::DIGITS.split(key.as_ref());
+        let node = self.arena.find(&digits)?;
+        self.arena[node].accept_state.as_ref()
+    }
+
+    /// returns a mutable reference to the value stored under key, if any.
+    pub fn get_mut(&mut self, key: impl AsRef<[u8]>) -> Option<&mut T> {
+        let digits = Self::DIGITS.split(key.as_ref());
+        let node = self.arena.find(&digits)?;
+        self.arena[node].accept_state.as_mut()
+    }
+
+    /// returns true if a value is stored under exactly this key.
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// stores value under key. If the key was already present,
+    /// the previous value is returned and the length is unchanged.
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: T) -> Option<T> {
         let buffer: Vec<u8> = key.into();
-        let mut iterator = buffer.into_iter();
-        self.root.insert(&mut iterator, value);
-        self.increment();
+        let digits = Self::DIGITS.split(&buffer);
+        let prev = self.arena.insert(&digits, value);
+        if prev.is_none() {
+            self.increment();
+        }
+        prev
+    }
+
+    /// locates the node for key once, returning an entry which can be
+    /// inspected, filled in or removed without walking the trie again.
+    pub fn entry<'a, K>(&'a mut self, key: &'a K) -> Entry<'a, T>
+    where
+        K: AsRef<[u8]> + ?Sized,
+    {
+        Entry::new(self, key.as_ref())
+    }
+
+    /// returns an iterator over the entries of the trie,
+    /// in lexicographic order of their keys. Use rev to walk
+    /// from the largest key downward.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self)
+    }
+
+    /// returns an iterator over the entries of the trie with
+    /// mutable references to the values, in key order.
+    ///
+    /// While a snapshot of the trie is alive, this first copies every
+    /// chunk of nodes the snapshot shares, values and all, whether or
+    /// not anything is written through the iterator.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut::new(self)
+    }
+
+    /// returns an iterator over the keys of the trie in order.
+    pub fn keys(&self) -> Keys<'_, T> {
+        Keys::new(self)
+    }
+
+    /// returns an iterator over the values of the trie in key order.
+    pub fn values(&self) -> Values<'_, T> {
+        Values::new(self)
+    }
+
+    /// returns an iterator over mutable references to the
+    /// values of the trie in key order. As with iter_mut, any
+    /// nodes shared with a live snapshot are copied first.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, T> {
+        ValuesMut::new(self)
+    }
+
+    /// returns an iterator over the entries whose keys start
+    /// with prefix, in key order. The prefix itself is included
+    /// if it is stored as a key.
+    pub fn iter_prefix(&self, prefix: impl AsRef<[u8]>) -> Prefix<'_, T> {
+        Prefix::new(self, prefix.as_ref())
+    }
+
+    /// returns an iterator over the keys which start with prefix, in order.
+    pub fn keys_with_prefix(&self, prefix: impl AsRef<[u8]>) -> PrefixKeys<'_, T> {
+        PrefixKeys::new(self, prefix.as_ref())
+    }
+
+    /// returns true if at least one key starts with prefix.
+    pub fn has_prefix(&self, prefix: impl AsRef<[u8]>) -> bool {
+        let digits = Self::DIGITS.split(prefix.as_ref());
+        self.arena
+            .find_prefix(&digits)
+            .is_some_and(|(node, _)| !self.arena[node].is_empty())
+    }
+
+    /// returns an iterator over the entries whose keys fall within
+    /// range, in key order. Subtrees outside of the range are skipped
+    /// without being visited.
+    /// As with BTreeMap, bounds on unsized slices are passed as a
+    /// pair, e.g. `(Bound::Included(&b"a"[..]), Bound::Excluded(&b"c"[..]))`.
+    ///
+    /// Panics if the start of the range is greater than its end, or if
+    /// both are equal and excluded.
+    pub fn range<R>(&self, range: R) -> Range<'_, T>
+    where
+        R: RangeBounds<[u8]>,
+    {
+        Range::new(self, range.start_bound(), range.end_bound())
+    }
+
+    /// returns the entry with the smallest key.
+    pub fn first_key_value(&self) -> Option<(Vec<u8>, &T)> {
+        self.iter().next()
+    }
+
+    /// returns the entry with the largest key.
+    pub fn last_key_value(&self) -> Option<(Vec<u8>, &T)> {
+        self.iter().next_back()
+    }
+
+    /// removes and returns the entry with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(Vec<u8>, T)> {
+        let (key, _) = self.first_key_value()?;
+        self.remove_entry(key)
+    }
+
+    /// removes and returns the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(Vec<u8>, T)> {
+        let (key, _) = self.last_key_value()?;
+        self.remove_entry(key)
+    }
+
+    /// returns the entry with the smallest key strictly greater than
+    /// key. The key itself need not be stored in the trie.
+    pub fn successor(&self, key: impl AsRef<[u8]>) -> Option<(Vec<u8>, &T)> {
+        self.range((Bound::Excluded(key.as_ref()), Bound::Unbounded))
+            .next()
+    }
+
+    /// returns the entry with the largest key strictly less than
+    /// key. The key itself need not be stored in the trie.
+    pub fn predecessor(&self, key: impl AsRef<[u8]>) -> Option<(Vec<u8>, &T)> {
+        self.range((Bound::Unbounded, Bound::Excluded(key.as_ref())))
+            .next_back()
+    }
+
+    /// returns the longest stored key which is a prefix of key,
+    /// as a slice of key, along with its value.
+    pub fn longest_prefix_match<'k, K>(&self, key: &'k K) -> Option<(&'k [u8], &T)>
+    where
+        K: AsRef<[u8]> + ?Sized,
+    {
+        self.common_prefix_search(key).last()
+    }
+
+    /// returns an iterator over every stored key which is a prefix
+    /// of key, shortest first. The walk stops as soon as key leaves
+    /// the trie, so this costs at most one step per byte of key.
+    pub fn common_prefix_search<'k, K>(&self, key: &'k K) -> CommonPrefixes<'_, 'k, T>
+    where
+        K: AsRef<[u8]> + ?Sized,
+    {
+        CommonPrefixes::new(self, key.as_ref())
+    }
+
+    /// removes the value stored under key and returns it.
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Option<T> {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// removes the value stored under key and returns it along with the key.
+    pub fn remove_entry(&mut self, key: impl AsRef<[u8]>) -> Option<(Vec<u8>, T)> {
+        let key = key.as_ref();
+        let digits = Self::DIGITS.split(key);
+        let value = self.arena.remove(&digits)?;
+        self.decrement();
+        Some((key.to_vec(), value))
+    }
+
+    /// walks the whole trie and returns a summary of its shape
+    /// and an estimate of its memory use.
+    pub fn stats(&self) -> Stats {
+        Stats::new(&self.arena, Self::DIGITS)
+    }
+
+    /// gives back memory the trie holds but no longer needs, as after
+    /// removing many keys. Removal already frees every node left with
+    /// no key beneath it; this also releases the slots those nodes
+    /// occupied, moves each node's children into the smallest layout
+    /// which holds them, and trims spare capacity from labels.
+    ///
+    /// Every node is moved, so while a snapshot is alive this copies
+    /// every chunk the two share and clones every value. The snapshot
+    /// keeps the old chunks until it is dropped.
+    pub fn shrink_to_fit(&mut self) {
+        self.arena.shrink_to_fit();
+    }
+
+    /// returns a read-only view of the trie as it is now, which is
+    /// unaffected by any later change to the trie. The two share their
+    /// nodes in chunks of 64, and a chunk is only copied when the trie
+    /// first writes to it while a snapshot still holds it. The first
+    /// snapshot moves the nodes into chunks; after that, taking a
+    /// snapshot costs one reference count per chunk.
+    ///
+    /// Walking the trie with iter_mut or values_mut while a snapshot is
+    /// alive copies every chunk, and so clones every value, even if
+    /// nothing is written. Dropping the snapshots first avoids this,
+    /// as chunks no snapshot holds are taken back without copying.
+    /// The same goes for shrink_to_fit, which moves every node.
+    pub fn snapshot(&mut self) -> TrieSnapshot<T, BITS>
+    where
+        T: Clone,
+    {
+        TrieSnapshot::new(Self {
+            arena: self.arena.share(),
+            len: self.len,
+        })
+    }
+
+    /// removes every key from the trie. Nodes are dropped one at a
+    /// time rather than recursively, so this is safe for tries of any
+    /// depth, as is dropping the trie itself.
+    pub fn clear(&mut self) {
+        self.arena.clear();
+        self.len = 0;
     }
 
     fn increment(&mut self) {
-        self.node_count += 1;
+        self.len += 1;
+    }
+
+    fn decrement(&mut self) {
+        self.len -= 1;
     }
 }
 
-struct RadixNode<T> {
-    /// If Some, a match occurs if there are no characters
-    /// remaining in the buffer. T is the value provided
-    /// during insertion.
-    accept_state: Option<T>,
+impl<T, const BITS: usize> Default for RadixTrie<T, BITS> {
+    fn default() -> Self {
+        Self::with_layout(Layout::default())
+    }
+}
 
-    /// children contains the collection of radix
-    /// nodes for which the bytes read thus far are a prefix.
-    /// This field is initialized lazily to conserve memory.
-    children: Option<Box<Level<T>>>,
+impl<T: fmt::Debug, const BITS: usize> fmt::Debug for RadixTrie<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
 }
 
-impl<T> RadixNode<T> {
-    pub fn new() -> Self {
-        Self {
-            accept_state: None,
-            children: None,
+/// Two tries are equal if they hold the same entries,
+/// whatever their layouts.
+impl<T: PartialEq, const BITS: usize> PartialEq for RadixTrie<T, BITS> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, const BITS: usize> Eq for RadixTrie<T, BITS> {}
+
+impl<T: Hash, const BITS: usize> Hash for RadixTrie<T, BITS> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for entry in self.iter() {
+            entry.hash(state);
+        }
+    }
+}
+
+/// Tries are compared by their entries in key order,
+/// as BTreeMaps are.
+impl<T: PartialOrd, const BITS: usize> PartialOrd for RadixTrie<T, BITS> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, const BITS: usize> Ord for RadixTrie<T, BITS> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K: Into<Vec<u8>>, T, const BITS: usize> FromIterator<(K, T)> for RadixTrie<T, BITS> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let mut trie = Self::default();
+        trie.extend(iter);
+        trie
+    }
+}
+
+/// Inserts each entry in turn, so a later value for
+/// a key replaces an earlier one.
+impl<K: Into<Vec<u8>>, T, const BITS: usize> Extend<(K, T)> for RadixTrie<T, BITS> {
+    fn extend<I: IntoIterator<Item = (K, T)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Looks up a key as with get.
+///
+/// Panics if the key is not present in the trie.
+impl<K, T, const BITS: usize> Index<&K> for RadixTrie<T, BITS>
+where
+    K: AsRef<[u8]> + ?Sized,
+{
+    type Output = T;
+
+    fn index(&self, key: &K) -> &T {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::arena::ROOT;
+    use crate::{BitTrie, Layout, NibbleTrie, RadixNode, RadixTrie};
+
+    fn sample() -> RadixTrie<u32> {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"".to_vec(), 0);
+        trie.insert(b"car".to_vec(), 1);
+        trie.insert(b"cart".to_vec(), 2);
+        trie.insert(b"cat".to_vec(), 3);
+        trie.insert(b"\0\xff".to_vec(), 4);
+        trie
+    }
+
+    #[test]
+    fn get_finds_exactly_the_keys_stored() {
+        let trie = sample();
+        assert_eq!(trie.get(b""), Some(&0));
+        assert_eq!(trie.get(b"car"), Some(&1));
+        assert_eq!(trie.get(b"cart"), Some(&2));
+        assert_eq!(trie.get("cat"), Some(&3));
+        assert_eq!(trie.get([0, 0xff]), Some(&4));
+        // prefixes and extensions of keys are not keys themselves
+        assert_eq!(trie.get(b"ca"), None);
+        assert_eq!(trie.get(b"carts"), None);
+        assert_eq!(trie.get(b"\0"), None);
+        assert_eq!(trie.get(b"dog"), None);
+    }
+
+    #[test]
+    fn contains_key_agrees_with_get() {
+        let trie = sample();
+        for key in [
+            &b""[..],
+            b"c",
+            b"ca",
+            b"car",
+            b"cart",
+            b"carts",
+            b"cat",
+            b"\0\xff",
+        ] {
+            assert_eq!(trie.contains_key(key), trie.get(key).is_some(), "{key:?}");
         }
+        assert!(!RadixTrie::<u32>::new().contains_key(b""));
     }
 
-    /// returns the item already in this position if the key matches
-    /// an existing key.
-    pub fn insert(&mut self, key: &mut dyn Iterator<Item = u8>, value: T) -> Option<T> {
-        match key.next() {
-            // Degenerate Case: We've reached the end of the string
-            // and can store the value in the accept state.
-            None => self.set_value(value),
-            // Recursive Case: We have at least one more byte to process.
-            Some(byte) => self.handle_next_byte(byte, key, value),
+    #[test]
+    fn get_mut_changes_the_stored_value() {
+        let mut trie = sample();
+        *trie.get_mut(b"car").unwrap() += 10;
+        *trie.get_mut(b"").unwrap() = 7;
+        assert!(trie.get_mut(b"ca").is_none());
+        assert_eq!(trie.get(b"car"), Some(&11));
+        assert_eq!(trie.get(b""), Some(&7));
+        assert_eq!(trie.get(b"cart"), Some(&2));
+    }
+
+    #[test]
+    fn insert_replaces_the_value() {
+        let mut trie = sample();
+        assert_eq!(trie.insert(b"cart".to_vec(), 20), Some(2));
+        assert_eq!(trie.insert(b"", 10), Some(0));
+        assert_eq!(trie.insert(b"ca".to_vec(), 5), None);
+        assert_eq!(trie.get(b"cart"), Some(&20));
+        assert_eq!(trie.get(b"car"), Some(&1));
+        assert_eq!(trie.get(b""), Some(&10));
+        assert_eq!(trie.len(), 6);
+    }
+
+    #[test]
+    fn len_counts_distinct_keys() {
+        let mut trie = RadixTrie::new();
+        assert_eq!(trie.len(), 0);
+        assert!(trie.is_empty());
+        for (i, key) in [&b"a"[..], b"ab", b"a", b"", b"ab", b""]
+            .into_iter()
+            .enumerate()
+        {
+            trie.insert(key, i);
         }
+        assert_eq!(trie.len(), 3);
+        assert_eq!(trie.remove(b"ab"), Some(4));
+        assert_eq!(trie.remove(b"ab"), None);
+        assert_eq!(trie.remove(b"abc"), None);
+        assert_eq!(trie.len(), 2);
+        trie.remove(b"a");
+        trie.remove(b"");
+        assert!(trie.is_empty());
     }
 
-    fn set_value(&mut self, value: T) -> Option<T> {
-        let prev = self.accept_state.take();
-        self.accept_state = Some(value);
-        prev
+    #[test]
+    fn node_count_follows_splits_and_merges() {
+        let mut trie = RadixTrie::new();
+        assert_eq!(trie.node_count(), 1);
+        // "" is stored in the root without allocating anything
+        trie.insert(b"", 0);
+        assert_eq!(trie.node_count(), 1);
+        // "abc" hangs off the root as a single edge labelled "bc"
+        trie.insert(b"abc", 1);
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.node_count(), 2);
+        // "abd" splits that edge after "ab", which gains two children
+        trie.insert(b"abd", 2);
+        assert_eq!(trie.node_count(), 4);
+        trie.insert(b"b", 3);
+        assert_eq!(trie.node_count(), 5);
+        // removing "abc" leaves "ab" with one child, so it is merged
+        trie.remove(b"abc");
+        assert_eq!(trie.node_count(), 3);
+        assert_eq!(trie.get(b"abd"), Some(&2));
+        trie.remove(b"abd");
+        trie.remove(b"b");
+        assert_eq!(trie.node_count(), 1);
+        assert_eq!(trie.len(), 1);
+        trie.remove(b"");
+        assert_eq!(trie.node_count(), 1);
+    }
+
+    #[test]
+    fn node_count_grows_with_keys_not_key_bytes() {
+        let count = |len: usize| {
+            let mut trie = RadixTrie::new();
+            for first in 0..=255u8 {
+                for second in [b'a', b'b'] {
+                    let mut key = vec![first, second];
+                    key.resize(len, first);
+                    trie.insert(key, ());
+                }
+            }
+            trie.node_count()
+        };
+        assert_eq!(count(2), count(3000));
+        // the root, then a branch and two leaves for each first byte
+        assert_eq!(count(2), 1 + 3 * 256);
+    }
+
+    #[test]
+    fn remove_takes_out_only_that_key() {
+        let mut trie = sample();
+        assert_eq!(trie.remove(b"car"), Some(1));
+        assert_eq!(trie.remove(b"car"), None);
+        assert_eq!(trie.get(b"car"), None);
+        assert_eq!(trie.get(b"cart"), Some(&2));
+        assert_eq!(trie.get(b"cat"), Some(&3));
+        assert_eq!(trie.remove(b"ca"), None);
+        assert_eq!(trie.remove(b"carts"), None);
+        assert_eq!(trie.remove(b"dog"), None);
+        assert_eq!(trie.remove(b""), Some(0));
+        assert_eq!(trie.get(b"cart"), Some(&2));
+    }
+
+    #[test]
+    fn remove_entry_returns_the_key() {
+        let mut trie = sample();
+        assert_eq!(trie.remove_entry(b"\0\xff"), Some((b"\0\xff".to_vec(), 4)));
+        assert_eq!(trie.remove_entry(b"\0\xff"), None);
+        assert_eq!(trie.remove_entry(b"ca"), None);
+    }
+
+    /// returns the node reached by key in a byte trie.
+    fn node_at<'a>(trie: &'a RadixTrie<u32>, key: &[u8]) -> Option<&'a RadixNode<u32>> {
+        trie.arena.find(key).map(|id| &trie.arena[id])
+    }
+
+    #[test]
+    fn remove_prunes_and_merges_nodes() {
+        let mut trie = RadixTrie::new();
+        trie.insert(b"abc".to_vec(), 1);
+        trie.insert(b"abd".to_vec(), 2);
+        assert_eq!(node_at(&trie, b"ab").unwrap().label, b"b");
+        // "ab" is left with the single child "d" and takes over its label
+        trie.remove(b"abc");
+        assert!(node_at(&trie, b"ab").is_none());
+        let node = node_at(&trie, b"abd").unwrap();
+        assert_eq!(node.label, b"bd");
+        assert!(node.children.is_none());
+        trie.remove(b"abd");
+        assert!(trie.arena[ROOT].children.is_none());
+
+        // A key still stored keeps the nodes above it.
+        trie.insert(b"a".to_vec(), 1);
+        trie.insert(b"abc".to_vec(), 2);
+        trie.remove(b"abc");
+        assert!(node_at(&trie, b"a").unwrap().children.is_none());
+        assert_eq!(trie.get(b"a"), Some(&1));
+        trie.remove(b"a");
+        assert!(trie.arena[ROOT].children.is_none());
+        assert_eq!(trie.node_count(), 1);
+    }
+
+    fn owned<K: AsRef<[u8]>>(entry: Option<(K, &u32)>) -> Option<(Vec<u8>, u32)> {
+        entry.map(|(k, &v)| (k.as_ref().to_vec(), v))
+    }
+
+    #[test]
+    fn first_and_last_follow_key_order() {
+        let mut trie = sample();
+        assert_eq!(owned(trie.first_key_value()), Some((b"".to_vec(), 0)));
+        assert_eq!(owned(trie.last_key_value()), Some((b"cat".to_vec(), 3)));
+        trie.insert(b"\xff".to_vec(), 5);
+        assert_eq!(owned(trie.last_key_value()), Some((b"\xff".to_vec(), 5)));
+        assert!(RadixTrie::<u32>::new().first_key_value().is_none());
+        assert!(RadixTrie::<u32>::new().last_key_value().is_none());
+    }
+
+    #[test]
+    fn pop_first_and_pop_last_drain_in_order() {
+        let mut trie = sample();
+        assert_eq!(trie.pop_first(), Some((b"".to_vec(), 0)));
+        assert_eq!(trie.pop_last(), Some((b"cat".to_vec(), 3)));
+        assert_eq!(trie.pop_first(), Some((b"\0\xff".to_vec(), 4)));
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.pop_last(), Some((b"cart".to_vec(), 2)));
+        assert_eq!(trie.pop_last(), Some((b"car".to_vec(), 1)));
+        assert_eq!(trie.pop_first(), None);
+        assert_eq!(trie.pop_last(), None);
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn successor_and_predecessor_of_stored_and_absent_keys() {
+        let trie = sample();
+        assert_eq!(owned(trie.successor(b"car")), Some((b"cart".to_vec(), 2)));
+        assert_eq!(owned(trie.predecessor(b"cart")), Some((b"car".to_vec(), 1)));
+        // absent keys: a prefix of stored keys, keys between them
+        // and keys past either end.
+        assert_eq!(owned(trie.successor(b"ca")), Some((b"car".to_vec(), 1)));
+        assert_eq!(
+            owned(trie.predecessor(b"ca")),
+            Some((b"\0\xff".to_vec(), 4))
+        );
+        assert_eq!(owned(trie.successor(b"carp")), Some((b"cart".to_vec(), 2)));
+        assert_eq!(
+            owned(trie.predecessor(b"carts")),
+            Some((b"cart".to_vec(), 2))
+        );
+        assert_eq!(owned(trie.successor(b"\0")), Some((b"\0\xff".to_vec(), 4)));
+        assert_eq!(owned(trie.predecessor(b"\0")), Some((b"".to_vec(), 0)));
+        assert_eq!(owned(trie.successor(b"cat")), None);
+        assert_eq!(owned(trie.successor(b"dog")), None);
+        assert_eq!(owned(trie.predecessor(b"")), None);
+        assert_eq!(owned(trie.predecessor(b"dog")), Some((b"cat".to_vec(), 3)));
+    }
+
+    #[test]
+    fn layouts_hold_the_same_entries() {
+        assert_eq!(RadixTrie::<u32>::new().layout(), Layout::Adaptive);
+        let mut bitmap = RadixTrie::<_>::with_layout(Layout::Bitmap);
+        assert_eq!(bitmap.layout(), Layout::Bitmap);
+        let mut adaptive = RadixTrie::new();
+        for i in 0..2000u32 {
+            let key = (i * 7919 % 1000).to_string();
+            assert_eq!(bitmap.insert(key.clone(), i), adaptive.insert(key, i));
+        }
+        for i in 0..500u32 {
+            let key = (i * 3).to_string();
+            assert_eq!(bitmap.remove(&key), adaptive.remove(&key));
+        }
+        assert_eq!(bitmap.len(), adaptive.len());
+        assert_eq!(bitmap.node_count(), adaptive.node_count());
+        assert!(bitmap.iter().eq(adaptive.iter()));
+    }
+
+    #[test]
+    fn narrow_digits_hold_the_same_entries() {
+        let mut bytes = RadixTrie::new();
+        let mut nibbles = NibbleTrie::default();
+        let mut bits = BitTrie::with_layout(Layout::Bitmap);
+        for i in 0..1000u32 {
+            let key = (i * 7919 % 600).to_be_bytes();
+            let key = &key[i as usize % 4..];
+            let prev = bytes.insert(key, i);
+            assert_eq!(nibbles.insert(key, i), prev);
+            assert_eq!(bits.insert(key, i), prev);
+        }
+        for i in 0..300u32 {
+            let key = i.to_be_bytes();
+            let key = &key[2..];
+            let removed = bytes.remove(key);
+            assert_eq!(nibbles.remove(key), removed);
+            assert_eq!(bits.remove(key), removed);
+        }
+        assert_eq!(nibbles.len(), bytes.len());
+        assert_eq!(bits.len(), bytes.len());
+        assert!(nibbles.iter().eq(bytes.iter()));
+        assert!(bits.iter().eq(bytes.iter()));
+        assert!(bits.iter().rev().eq(bytes.iter().rev()));
+        for (key, value) in bytes.iter() {
+            assert_eq!(nibbles.get(&key), Some(value));
+            assert_eq!(bits.get(&key), Some(value));
+        }
+        assert!(bits.has_prefix([0, 0]));
+        assert_eq!(
+            bits.keys_with_prefix([2]).count(),
+            bytes.keys_with_prefix([2]).count()
+        );
+    }
+
+    /// runs f on a thread with a stack far smaller than the depth of
+    /// the tries it builds, so any recursion over their nodes overflows.
+    fn on_small_stack(f: impl FnOnce() + Send + 'static) {
+        std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn deep_tries_do_not_overflow_the_stack() {
+        on_small_stack(|| {
+            let mut trie = RadixTrie::new();
+            let long = vec![7u8; 4 << 20];
+            trie.insert(long.clone(), 0);
+            // every key is a prefix of the next, so each adds a level
+            let depth = 3000;
+            for len in 1..=depth {
+                trie.insert(vec![1u8; len], len);
+            }
+            assert_eq!(trie.len(), depth + 1);
+            assert_eq!(trie.get(&long), Some(&0));
+            assert_eq!(trie.get(vec![1u8; depth]), Some(&depth));
+            assert_eq!(trie.iter().count(), depth + 1);
+            assert_eq!(trie.iter().next_back().unwrap().0, long);
+            assert_eq!(trie.remove(&long), Some(0));
+            assert_eq!(trie.remove(vec![1u8; 1]), Some(1));
+            assert_eq!(trie.get(vec![1u8; 2]), Some(&2));
+            trie.clear();
+            assert!(trie.is_empty());
+            for len in 1..=depth {
+                trie.insert(vec![1u8; len], len);
+            }
+            drop(trie);
+        });
     }
 
-    fn handle_next_byte(
-        &mut self,
-        byte: u8,
-        key: &mut dyn Iterator<Item = u8>,
-        value: T,
-    ) -> Option<T> {
-        // • Check if the array has been initialized.
-        if self.children.is_none() {
-            // • If not, initialize it with a collection of empty cells.
-            self.children = Some(Self::new_children());
-        }
-
-        // • Insert this item at the given position.
-        match self.children.as_mut() {
-            Some(children) => children[byte as usize].insert(key, value),
-            None => {
-                let mut children = Self::new_children();
-                let found = children[byte as usize].insert(key, value);
-                self.children = Some(children);
-                found
+    #[test]
+    fn clear_empties_the_trie_for_reuse() {
+        let mut trie = sample();
+        trie.clear();
+        assert!(trie.is_empty());
+        assert_eq!(trie.node_count(), 1);
+        assert_eq!(trie.get(b""), None);
+        assert_eq!(trie.get(b"car"), None);
+        assert!(trie.iter().next().is_none());
+        trie.insert(b"cart".to_vec(), 5);
+        assert_eq!(trie.len(), 1);
+        assert_eq!(trie.get(b"cart"), Some(&5));
+        assert_eq!(trie.node_count(), 2);
+    }
+
+    #[test]
+    fn shrink_to_fit_keeps_the_contents_and_frees_memory() {
+        let mut trie = RadixTrie::new();
+        for i in 0..20_000u32 {
+            trie.insert(i.to_be_bytes(), i);
+        }
+        // keep every thousandth key, and 40 of the 256 children of the
+        // node for [0, 0, 1], which is too many for it to leave Node256
+        for i in 0..20_000u32 {
+            if i % 1000 != 0 && !(256..296).contains(&i) {
+                trie.remove(i.to_be_bytes());
             }
         }
+        let expected: Vec<_> = trie.iter().map(|(k, &v)| (k, v)).collect();
+        let before = trie.stats();
+        assert_eq!(before.node256, 1);
+
+        trie.shrink_to_fit();
+        let after = trie.stats();
+        assert!(trie
+            .iter()
+            .map(|(k, &v)| (k, v))
+            .eq(expected.iter().cloned()));
+        for (key, value) in &expected {
+            assert_eq!(trie.get(key), Some(value));
+        }
+        assert_eq!(after.keys, before.keys);
+        assert_eq!(after.nodes, before.nodes);
+        assert_eq!(after.node256, 0);
+        assert_eq!(after.node48, before.node48 + 1);
+        assert!(after.heap_bytes < before.heap_bytes / 10);
+
+        // the trie is still usable afterwards
+        trie.insert(7u32.to_be_bytes(), 7);
+        assert_eq!(trie.remove(256u32.to_be_bytes()), Some(256));
+        assert_eq!(trie.get(7u32.to_be_bytes()), Some(&7));
+        assert_eq!(trie.len(), expected.len());
     }
 
-    /// allocates a new array of radix nodes.
-    fn new_children() -> Box<Level<T>> {
-        let mut children_vec = Vec::with_capacity(BRANCH_FACTOR);
+    fn hash_of(trie: &RadixTrie<u32>) -> u64 {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        trie.hash(&mut hasher);
+        hasher.finish()
+    }
 
-        for _ in 0..BRANCH_FACTOR {
-            children_vec.push(RadixNode::default());
+    #[test]
+    fn debug_prints_a_map_in_key_order() {
+        let trie: RadixTrie<u32> = [(&b"cat"[..], 3), (b"car", 1)].into_iter().collect();
+        assert_eq!(format!("{trie:?}"), "{[99, 97, 114]: 1, [99, 97, 116]: 3}");
+        assert_eq!(format!("{:?}", RadixTrie::<u32>::new()), "{}");
+    }
+
+    #[test]
+    fn equal_tries_hold_the_same_entries_whatever_their_layouts() {
+        let adaptive = sample();
+        let mut bitmap = RadixTrie::<_>::with_layout(Layout::Bitmap);
+        bitmap.extend(sample());
+        assert_eq!(adaptive, bitmap);
+        assert_eq!(hash_of(&adaptive), hash_of(&bitmap));
+
+        // A trie which once held more keys may have a different
+        // shape in its arena, but compares and hashes the same.
+        let mut shrunk = sample();
+        shrunk.insert(b"dog".to_vec(), 9);
+        shrunk.remove(b"dog");
+        assert_eq!(adaptive, shrunk);
+        assert_eq!(hash_of(&adaptive), hash_of(&shrunk));
+
+        let mut changed = sample();
+        *changed.get_mut(b"cat").unwrap() += 1;
+        assert_ne!(adaptive, changed);
+        changed.remove(b"cat");
+        assert_ne!(adaptive, changed);
+    }
+
+    #[test]
+    fn ordering_matches_btreemap() {
+        let sets: [&[(&[u8], u32)]; 7] = [
+            &[],
+            &[(b"", 0)],
+            &[(b"a", 1)],
+            &[(b"a", 2)],
+            &[(b"a", 1), (b"b", 1)],
+            &[(b"ab", 1)],
+            &[(b"b", 0)],
+        ];
+        for a in sets {
+            for b in sets {
+                let (ta, tb): (RadixTrie<u32>, RadixTrie<u32>) =
+                    (a.iter().copied().collect(), b.iter().copied().collect());
+                let (ma, mb): (BTreeMap<_, _>, BTreeMap<_, _>) =
+                    (a.iter().copied().collect(), b.iter().copied().collect());
+                assert_eq!(ta.cmp(&tb), ma.cmp(&mb), "{a:?} against {b:?}");
+                assert_eq!(ta.partial_cmp(&tb), ma.partial_cmp(&mb));
+            }
         }
-        let children: [RadixNode<T>; BRANCH_FACTOR] =
-            children_vec.try_into().unwrap_or_else(|_| unreachable!());
-        Box::new(children)
     }
-}
 
-impl<T> Default for RadixNode<T> {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn collecting_and_extending_keep_the_last_duplicate() {
+        let mut trie: RadixTrie<u32> = [("a", 1), ("b", 2), ("a", 3)].into_iter().collect();
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie[b"a"], 3);
+        trie.extend([("b", 4), ("c", 5), ("b", 6)]);
+        assert_eq!(trie.len(), 3);
+        assert_eq!(trie["b"], 6);
+        assert_eq!(trie[&b"c".to_vec()], 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry found for key")]
+    fn index_panics_on_a_missing_key() {
+        let _ = sample()[b"ca"];
     }
 }
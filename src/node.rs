@@ -0,0 +1,225 @@
+use crate::arena::{Arena, NodeId, ROOT};
+use crate::children::Children;
+
+/// the most children a node can have, reached with 8-bit digits.
+pub(crate) const BRANCH_FACTOR: usize = 256;
+
+#[derive(Clone)]
+pub(crate) struct RadixNode<T> {
+    /// the bytes along the edge into this node which follow the
+    /// byte selecting it among its parent's children. Chains of
+    /// nodes without a value and with a single child are collapsed
+    /// into one node with a longer label, so a node is only ever
+    /// allocated where keys branch or end. The root's label is empty.
+    pub(crate) label: Vec<u8>,
+
+    /// If Some, a match occurs if there are no characters
+    /// remaining in the buffer. T is the value provided
+    /// during insertion.
+    pub(crate) accept_state: Option<T>,
+
+    /// children contains the ids of the radix nodes
+    /// for which the bytes read thus far are a prefix.
+    /// This field is initialized lazily to conserve memory,
+    /// and is None again once the last child is removed.
+    pub(crate) children: Option<Children>,
+}
+
+impl<T> RadixNode<T> {
+    pub fn new() -> Self {
+        Self::with_label(Vec::new())
+    }
+
+    pub(crate) fn with_label(label: Vec<u8>) -> Self {
+        Self {
+            label,
+            accept_state: None,
+            children: None,
+        }
+    }
+
+    pub(crate) fn child(&self, byte: u8) -> Option<NodeId> {
+        self.children.as_ref()?.get(byte)
+    }
+
+    /// a node is empty if it neither accepts nor leads to a node which does.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.accept_state.is_none() && self.children.is_none()
+    }
+
+    fn set_value(&mut self, value: T) -> Option<T> {
+        let prev = self.accept_state.take();
+        self.accept_state = Some(value);
+        prev
+    }
+}
+
+impl<T> Default for RadixNode<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The operations which walk and reshape the tree. They live on the
+/// arena rather than on RadixNode, as nodes reach their children
+/// through it.
+impl<T> Arena<T> {
+    /// stores value under key, returning the item already in this
+    /// position if the key matches an existing key.
+    pub(crate) fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        let node = self.find_or_create(ROOT, key);
+        self[node].set_value(value)
+    }
+
+    /// walks the children one edge at a time and returns the node
+    /// reached from the root after consuming the whole key, if it exists.
+    pub(crate) fn find(&self, key: &[u8]) -> Option<NodeId> {
+        let mut node = ROOT;
+        let mut rest = key;
+        while let Some((&byte, tail)) = rest.split_first() {
+            node = self[node].child(byte)?;
+            rest = tail.strip_prefix(self[node].label.as_slice())?;
+        }
+        Some(node)
+    }
+
+    /// returns the topmost node whose key starts with prefix, along
+    /// with the part of its label which extends past the prefix.
+    pub(crate) fn find_prefix(&self, prefix: &[u8]) -> Option<(NodeId, &[u8])> {
+        let mut node = ROOT;
+        let mut rest = prefix;
+        while let Some((&byte, tail)) = rest.split_first() {
+            node = self[node].child(byte)?;
+            let label = &self[node].label;
+            match tail.strip_prefix(label.as_slice()) {
+                Some(after) => rest = after,
+                None if label.starts_with(tail) => return Some((node, &label[tail.len()..])),
+                None => return None,
+            }
+        }
+        Some((node, &[]))
+    }
+
+    /// like find, but starts from node and creates the node for key if
+    /// it is missing, splitting any edge the key diverges from on the way.
+    pub(crate) fn find_or_create(&mut self, mut node: NodeId, key: &[u8]) -> NodeId {
+        let mut rest = key;
+        while let Some((&byte, tail)) = rest.split_first() {
+            let (child, consumed) = self.make_child(node, byte, tail);
+            rest = &tail[consumed..];
+            node = child;
+        }
+        node
+    }
+
+    /// removes the value stored under key. Any node left without a
+    /// value or children of its own is freed on the way back up, and
+    /// any left with a single child is merged into it.
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Option<T> {
+        // • Walk down to the key, remembering every edge taken.
+        let mut path = Vec::new();
+        let mut node = ROOT;
+        let mut rest = key;
+        while let Some((&byte, tail)) = rest.split_first() {
+            let child = self[node].child(byte)?;
+            rest = tail.strip_prefix(self[child].label.as_slice())?;
+            path.push((node, byte));
+            node = child;
+        }
+        let removed = self[node].accept_state.take()?;
+
+        // • Walk back up, pruning. Once a node survives, the
+        //   nodes above it keep their shape and the walk can stop.
+        for (parent, byte) in path.into_iter().rev() {
+            if !self[node].is_empty() {
+                self.compact(node);
+                break;
+            }
+            let upper = &mut self[parent];
+            let children = upper.children.as_mut().expect("child was just found");
+            children.remove(byte);
+            if children.is_empty() {
+                upper.children = None;
+            }
+            self.free(node);
+            node = parent;
+        }
+        Some(removed)
+    }
+
+    /// returns the child of node through which a key continues with
+    /// byte followed by tail, along with the number of bytes of tail
+    /// its label covers. A new child takes all of tail as its label, and
+    /// a child whose label diverges from tail is split where they part.
+    fn make_child(&mut self, node: NodeId, byte: u8, tail: &[u8]) -> (NodeId, usize) {
+        let child = match self[node].child(byte) {
+            Some(child) => child,
+            None => {
+                let child = self.alloc(RadixNode::with_label(tail.to_vec()));
+                let layout = self.layout;
+                // • Check if the children have been initialized.
+                self[node]
+                    .children
+                    .get_or_insert_with(|| Children::new(layout))
+                    .insert(byte, child);
+                return (child, tail.len());
+            }
+        };
+        let label = &self[child].label;
+        let common = common_prefix_len(label, tail);
+        if common < label.len() {
+            self.split(child, common);
+        }
+        (child, common)
+    }
+
+    /// shortens the label of node to its first at bytes, moving its
+    /// value and children into a new child which carries the rest
+    /// of the label.
+    fn split(&mut self, node: NodeId, at: usize) {
+        let upper = &mut self[node];
+        let tail = upper.label.split_off(at + 1);
+        let byte = upper
+            .label
+            .pop()
+            .expect("split point lies within the label");
+        let lower = RadixNode {
+            label: tail,
+            accept_state: upper.accept_state.take(),
+            children: upper.children.take(),
+        };
+
+        let mut children = Children::new(self.layout);
+        children.insert(byte, self.alloc(lower));
+        self[node].children = Some(children);
+    }
+
+    /// restores the shape of a node without a value after a removal
+    /// beneath it, by merging it with its only remaining child.
+    fn compact(&mut self, node: NodeId) {
+        let parent = &mut self[node];
+        if parent.accept_state.is_some() {
+            return;
+        }
+        let Some(children) = parent.children.as_mut() else {
+            return;
+        };
+        if children.len() != 1 {
+            return;
+        }
+
+        let byte = children.first_byte().expect("one child remains");
+        let child = children.remove(byte).expect("one child remains");
+        let child = self.free(child);
+        let parent = &mut self[node];
+        parent.label.push(byte);
+        parent.label.extend_from_slice(&child.label);
+        parent.accept_state = child.accept_state;
+        parent.children = child.children;
+    }
+}
+
+/// returns the number of leading bytes a and b have in common.
+pub(crate) fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
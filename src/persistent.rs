@@ -0,0 +1,484 @@
+use std::fmt;
+use std::iter::FusedIterator;
+use std::mem;
+use std::sync::Arc;
+
+use crate::node::common_prefix_len;
+
+/// An immutable map from byte strings to values of type T, stored as
+/// a radix tree whose nodes are reference counted. Inserting or
+/// removing a key returns a new version of the trie which copies only
+/// the nodes on the path to that key and shares every other subtree
+/// with the version it was made from, so keeping old versions around
+/// costs O(depth) nodes per change rather than a copy of the whole trie.
+///
+/// Values are reference counted too, so are never cloned. Cloning the
+/// trie itself only takes another reference to its root.
+pub struct PersistentRadixTrie<T> {
+    root: Arc<Node<T>>,
+    /// the number of distinct keys stored in the trie.
+    len: usize,
+}
+
+/// A node of a PersistentRadixTrie, with the same shape as a
+/// RadixNode: a non-root node holds a value or at least two children.
+struct Node<T> {
+    /// the bytes along the edge into this node which follow the
+    /// byte selecting it among its parent's children.
+    label: Vec<u8>,
+    value: Option<Arc<T>>,
+    /// the children in ascending order of the byte selecting them.
+    children: Vec<(u8, Arc<Node<T>>)>,
+}
+
+impl<T> Node<T> {
+    fn new(label: Vec<u8>) -> Self {
+        Self {
+            label,
+            value: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&self, byte: u8) -> Option<&Arc<Node<T>>> {
+        let position = self.position(byte).ok()?;
+        Some(&self.children[position].1)
+    }
+
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.children.binary_search_by_key(&byte, |&(key, _)| key)
+    }
+
+    /// makes child the child under byte, adding it or replacing the
+    /// one there, or removes the child under byte if child is None.
+    fn set_child(&mut self, byte: u8, child: Option<Node<T>>) {
+        match (self.position(byte), child) {
+            (Ok(position), Some(child)) => self.children[position].1 = Arc::new(child),
+            (Err(position), Some(child)) => self.children.insert(position, (byte, Arc::new(child))),
+            (Ok(position), None) => drop(self.children.remove(position)),
+            (Err(_), None) => {}
+        }
+    }
+
+    /// returns a copy of the node which shares its value and
+    /// children, but for the child under byte, as by set_child.
+    fn with_child(&self, byte: u8, child: Option<Node<T>>) -> Self {
+        let mut node = self.clone();
+        node.set_child(byte, child);
+        node
+    }
+
+    /// restores the shape of a non-root node after a removal from it or
+    /// beneath it, by dropping it if it is empty and merging it with its
+    /// only child if it has no value of its own.
+    fn compact(mut self) -> Option<Self> {
+        if self.value.is_some() || self.children.len() > 1 {
+            return Some(self);
+        }
+        let (byte, child) = self.children.pop()?;
+        self.label.push(byte);
+        self.label.extend_from_slice(&child.label);
+        self.value = child.value.clone();
+        self.children = child.children.clone();
+        Some(self)
+    }
+}
+
+/// The copy shares the value and children of the original.
+impl<T> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        Self {
+            label: self.label.clone(),
+            value: self.value.clone(),
+            children: self.children.clone(),
+        }
+    }
+}
+
+/// Nodes which are not shared with another version are dropped one
+/// after another rather than recursively, however deep the trie is.
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+        while let Some((_, child)) = stack.pop() {
+            if let Some(mut child) = Arc::into_inner(child) {
+                stack.append(&mut child.children);
+            }
+        }
+    }
+}
+
+impl<T> PersistentRadixTrie<T> {
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(Node::new(Vec::new())),
+            len: 0,
+        }
+    }
+
+    /// returns the number of distinct keys stored in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// returns a reference to the value stored under key, if any.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&T> {
+        let mut node = &*self.root;
+        let mut rest = key.as_ref();
+        while let Some((&byte, tail)) = rest.split_first() {
+            node = node.child(byte)?;
+            rest = tail.strip_prefix(node.label.as_slice())?;
+        }
+        node.value.as_deref()
+    }
+
+    /// returns true if a value is stored under exactly this key.
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// returns a new version of the trie with value stored under key,
+    /// replacing any value already there. The trie itself is unchanged.
+    pub fn insert(&self, key: impl AsRef<[u8]>, value: T) -> Self {
+        let value = Some(Arc::new(value));
+        let mut len = self.len + 1;
+
+        // • Walk down to the node for key, remembering every edge
+        //   taken, and make a new version of the last node reached.
+        let mut path = Vec::new();
+        let mut node = &*self.root;
+        let mut rest = key.as_ref();
+        let mut changed = loop {
+            let Some((&byte, tail)) = rest.split_first() else {
+                if node.value.is_some() {
+                    len -= 1;
+                }
+                let mut changed = node.clone();
+                changed.value = value;
+                break changed;
+            };
+            let Some(child) = node.child(byte) else {
+                let mut leaf = Node::new(tail.to_vec());
+                leaf.value = value;
+                break node.with_child(byte, Some(leaf));
+            };
+            let common = common_prefix_len(&child.label, tail);
+            if common == child.label.len() {
+                path.push((node, byte));
+                node = child;
+                rest = &tail[common..];
+                continue;
+            }
+
+            // The key leaves the edge into child partway along,
+            // so a new node is put in where they part.
+            let mut middle = Node::new(tail[..common].to_vec());
+            let mut lower = (**child).clone();
+            lower.label.drain(..common + 1);
+            middle.set_child(child.label[common], Some(lower));
+            match tail[common..].split_first() {
+                None => middle.value = value,
+                Some((&next, label)) => {
+                    let mut leaf = Node::new(label.to_vec());
+                    leaf.value = value;
+                    middle.set_child(next, Some(leaf));
+                }
+            }
+            break node.with_child(byte, Some(middle));
+        };
+
+        // • Copy the nodes above it, each pointing to the new
+        //   version of the node below. All else is shared.
+        for (upper, byte) in path.into_iter().rev() {
+            changed = upper.with_child(byte, Some(changed));
+        }
+        Self {
+            root: Arc::new(changed),
+            len,
+        }
+    }
+
+    /// returns a new version of the trie without key, or a copy of
+    /// this one if key is not present. The trie itself is unchanged.
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> Self {
+        // • Walk down to the key, remembering every edge taken.
+        let mut path = Vec::new();
+        let mut node = &*self.root;
+        let mut rest = key.as_ref();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let Some(child) = node.child(byte) else {
+                return self.clone();
+            };
+            let Some(after) = tail.strip_prefix(child.label.as_slice()) else {
+                return self.clone();
+            };
+            path.push((node, byte));
+            node = child;
+            rest = after;
+        }
+        if node.value.is_none() {
+            return self.clone();
+        }
+
+        // • Copy the nodes back up to the root, pruning as in
+        //   RadixTrie::remove. The root is never pruned or merged.
+        let mut changed = node.clone();
+        changed.value = None;
+        for (upper, byte) in path.into_iter().rev() {
+            changed = upper.with_child(byte, changed.compact());
+        }
+        Self {
+            root: Arc::new(changed),
+            len: self.len - 1,
+        }
+    }
+
+    /// returns an iterator over the entries of the trie,
+    /// in lexicographic order of their keys.
+    pub fn iter(&self) -> PersistentIter<'_, T> {
+        PersistentIter {
+            stack: vec![(&*self.root, Vec::new())],
+            remaining: self.len,
+        }
+    }
+}
+
+impl<T> Clone for PersistentRadixTrie<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: Arc::clone(&self.root),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Default for PersistentRadixTrie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PersistentRadixTrie<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: AsRef<[u8]>, T> FromIterator<(K, T)> for PersistentRadixTrie<T> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |trie, (key, value)| trie.insert(key, value))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a PersistentRadixTrie<T> {
+    type Item = (Vec<u8>, &'a T);
+    type IntoIter = PersistentIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a PersistentRadixTrie in key order.
+pub struct PersistentIter<'a, T> {
+    /// the nodes still to visit, the next on top, each with its key.
+    stack: Vec<(&'a Node<T>, Vec<u8>)>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for PersistentIter<'a, T> {
+    type Item = (Vec<u8>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, key)) = self.stack.pop() {
+            for (byte, child) in node.children.iter().rev() {
+                let mut key = key.clone();
+                key.push(*byte);
+                key.extend_from_slice(&child.label);
+                self.stack.push((child, key));
+            }
+            if let Some(value) = &node.value {
+                self.remaining -= 1;
+                return Some((key, value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for PersistentIter<'_, T> {}
+impl<T> FusedIterator for PersistentIter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use super::{Node, PersistentRadixTrie};
+
+    /// returns the byte and label of each edge out of node.
+    fn edges(node: &Node<u32>) -> Vec<(u8, &[u8])> {
+        node.children
+            .iter()
+            .map(|(byte, child)| (*byte, child.label.as_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn changes_leave_older_versions_unchanged() {
+        let empty = PersistentRadixTrie::new();
+        let one = empty.insert(b"car", 1);
+        let two = one.insert(b"cat", 2);
+        let three = two.insert(b"car", 3);
+        let four = three.remove(b"cat");
+
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(b"car"), None);
+        assert_eq!(one.get(b"car"), Some(&1));
+        assert_eq!(one.get(b"cat"), None);
+        assert_eq!((two.get(b"car"), two.get(b"cat")), (Some(&1), Some(&2)));
+        assert_eq!((three.get(b"car"), three.get(b"cat")), (Some(&3), Some(&2)));
+        assert_eq!((four.get(b"car"), four.get(b"cat")), (Some(&3), None));
+        assert!(!four.contains_key(b"ca"));
+
+        // The subtree under "cat" was not on the path to "car",
+        // so the second and third versions share it.
+        let shared = |trie: &PersistentRadixTrie<u32>| {
+            let ca = trie.root.child(b'c').unwrap();
+            Arc::clone(ca.child(b't').unwrap())
+        };
+        assert!(Arc::ptr_eq(&shared(&two), &shared(&three)));
+    }
+
+    #[test]
+    fn insert_splits_an_edge_partway_along_its_label() {
+        let trie = PersistentRadixTrie::new().insert(b"interstellar", 1);
+        assert_eq!(edges(&trie.root), [(b'i', &b"nterstellar"[..])]);
+
+        let trie = trie.insert(b"internal", 2);
+        assert_eq!(edges(&trie.root), [(b'i', &b"nter"[..])]);
+        let middle = trie.root.child(b'i').unwrap();
+        assert_eq!(middle.value, None);
+        assert_eq!(edges(middle), [(b'n', &b"al"[..]), (b's', b"tellar")]);
+
+        // A key ending where the edges part lands on the new node.
+        let trie = trie.insert(b"inter", 3);
+        assert_eq!(edges(&trie.root), [(b'i', &b"nter"[..])]);
+        assert_eq!(trie.root.child(b'i').unwrap().value.as_deref(), Some(&3));
+
+        // A key ending partway along a label splits it with no new leaf.
+        let trie = trie.insert(b"in", 4);
+        let upper = trie.root.child(b'i').unwrap();
+        assert_eq!(upper.label, b"n");
+        assert_eq!(edges(upper), [(b't', &b"er"[..])]);
+        for (key, value) in [
+            (&b"interstellar"[..], 1),
+            (b"internal", 2),
+            (b"inter", 3),
+            (b"in", 4),
+        ] {
+            assert_eq!(trie.get(key), Some(&value));
+        }
+        assert_eq!(trie.get(b"int"), None);
+    }
+
+    #[test]
+    fn remove_merges_and_prunes_nodes() {
+        let trie: PersistentRadixTrie<u32> =
+            [(&b"inter"[..], 1), (b"internal", 2), (b"interstellar", 3)]
+                .into_iter()
+                .collect();
+
+        // With two children left, the node for "inter" stays.
+        let trie = trie.remove(b"inter");
+        let middle = trie.root.child(b'i').unwrap();
+        assert_eq!(middle.label, b"nter");
+        assert_eq!(middle.value, None);
+        assert_eq!(middle.children.len(), 2);
+
+        // With one, it is merged into its child.
+        let trie = trie.remove(b"internal");
+        assert_eq!(edges(&trie.root), [(b'i', &b"nterstellar"[..])]);
+        assert_eq!(trie.get(b"interstellar"), Some(&3));
+
+        // With none, it is pruned, but the root remains.
+        let trie = trie.remove(b"interstellar");
+        assert!(trie.root.children.is_empty());
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn len_counts_distinct_keys() {
+        let trie = PersistentRadixTrie::new().insert(b"a", 1).insert(b"b", 2);
+        assert_eq!(trie.len(), 2);
+        let overwritten = trie.insert(b"a", 3);
+        assert_eq!(overwritten.len(), 2);
+        assert_eq!(overwritten.get(b"a"), Some(&3));
+
+        // Removing a missing key, or one only a prefix of a stored
+        // key, gives back the same version.
+        for key in [&b"c"[..], b"", b"ab"] {
+            let same = overwritten.remove(key);
+            assert_eq!(same.len(), 2);
+            assert!(Arc::ptr_eq(&same.root, &overwritten.root));
+        }
+        assert_eq!(overwritten.remove(b"a").len(), 1);
+        assert_eq!(trie.len(), 2);
+    }
+
+    #[test]
+    fn iter_visits_keys_in_order() {
+        let keys = [&b"cat"[..], b"", b"car", b"\0\xff", b"cart", b"\xff", b"c"];
+        let trie: PersistentRadixTrie<usize> =
+            keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+        let expected: BTreeMap<Vec<u8>, usize> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.to_vec(), i))
+            .collect();
+
+        let iter = trie.iter();
+        assert_eq!(iter.len(), keys.len());
+        let entries: Vec<_> = iter.map(|(key, value)| (key, *value)).collect();
+        assert!(entries.into_iter().eq(expected));
+        assert_eq!((&trie).into_iter().count(), keys.len());
+        assert_eq!(PersistentRadixTrie::<u32>::new().iter().next(), None);
+    }
+
+    #[test]
+    fn dropping_a_deep_trie_does_not_overflow_the_stack() {
+        let drop_deep = || {
+            // every key is a prefix of the next, so each adds a level
+            let depth = 3000;
+            let mut trie = PersistentRadixTrie::new();
+            let mut old = Vec::new();
+            for len in 1..=depth {
+                trie = trie.insert(vec![1u8; len], len);
+                if len % 1000 == 0 {
+                    old.push(trie.clone());
+                }
+            }
+            assert_eq!(trie.len(), depth);
+            assert_eq!(trie.get(vec![1u8; depth]), Some(&depth));
+            // The older versions share most of the chain, which
+            // is only dropped with the last of them.
+            drop(trie);
+            drop(old);
+        };
+        std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(drop_deep)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::ops::Deref;
+
+use crate::RadixTrie;
+
+/// A read-only view of a RadixTrie as it was when RadixTrie::snapshot
+/// was called, which later changes to the trie do not affect. It
+/// dereferences to the trie as it was, so every method taking &self,
+/// from get to iter_prefix, works on it. A snapshot is Send and Sync
+/// whenever T is, so it can be handed to reader threads while the
+/// trie goes on changing in its own.
+#[derive(Clone)]
+pub struct TrieSnapshot<T, const BITS: usize = 8> {
+    trie: RadixTrie<T, BITS>,
+}
+
+/// Snapshots are meant to be handed to other threads, so they must
+/// stay Send and Sync whenever T is.
+const _: fn() = || {
+    fn send_sync<X: Send + Sync>() {}
+    send_sync::<TrieSnapshot<u32>>();
+    send_sync::<TrieSnapshot<u32, 1>>();
+};
+
+impl<T, const BITS: usize> TrieSnapshot<T, BITS> {
+    pub(crate) fn new(trie: RadixTrie<T, BITS>) -> Self {
+        Self { trie }
+    }
+}
+
+impl<T, const BITS: usize> Deref for TrieSnapshot<T, BITS> {
+    type Target = RadixTrie<T, BITS>;
+
+    fn deref(&self) -> &RadixTrie<T, BITS> {
+        &self.trie
+    }
+}
+
+impl<T: fmt::Debug, const BITS: usize> fmt::Debug for TrieSnapshot<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.trie.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+    use std::thread;
+
+    use crate::{Layout, RadixTrie, TrieSnapshot};
+
+    /// enough keys to fill many chunks, sharing prefixes.
+    fn keys(count: u32) -> impl Iterator<Item = Vec<u8>> {
+        (0..count).map(|key| format!("key/{}/{key}", key % 7).into_bytes())
+    }
+
+    fn entries<const BITS: usize>(trie: &RadixTrie<u32, BITS>) -> BTreeMap<Vec<u8>, u32> {
+        trie.iter().map(|(key, &value)| (key, value)).collect()
+    }
+
+    fn unchanged_by_later_changes<const BITS: usize>(mut trie: RadixTrie<u32, BITS>) {
+        for (value, key) in (0..).zip(keys(1000)) {
+            trie.insert(key, value);
+        }
+        let before = entries(&trie);
+        let snapshot = trie.snapshot();
+        let check = |snapshot: &TrieSnapshot<u32, BITS>| {
+            assert_eq!(entries(snapshot), before);
+            assert_eq!(snapshot.len(), before.len());
+            assert_eq!(snapshot.get(b"key/3/10"), Some(&10));
+        };
+
+        let mut expected = before.clone();
+        for (value, key) in (5000..).zip(keys(1200).skip(900)) {
+            expected.insert(key.clone(), value);
+            trie.insert(key, value);
+        }
+        check(&snapshot);
+        for key in keys(1000).step_by(3) {
+            assert_eq!(trie.remove(&key), expected.remove(&key));
+        }
+        check(&snapshot);
+        let second = trie.snapshot();
+        for (_, value) in trie.iter_mut() {
+            *value += 1;
+        }
+        for value in expected.values_mut() {
+            *value += 1;
+        }
+        check(&snapshot);
+        trie.shrink_to_fit();
+        check(&snapshot);
+        assert_eq!(entries(&trie), expected);
+        assert_eq!(entries(&second).len(), expected.len());
+        assert!(entries(&second)
+            .iter()
+            .all(|(key, value)| expected[key] == value + 1));
+        trie.clear();
+        check(&snapshot);
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn snapshot_is_unchanged_by_later_changes() {
+        unchanged_by_later_changes(RadixTrie::<_>::with_layout(Layout::Adaptive));
+        unchanged_by_later_changes(RadixTrie::<_>::with_layout(Layout::Bitmap));
+        unchanged_by_later_changes(RadixTrie::<_, 1>::with_layout(Layout::Adaptive));
+    }
+
+    #[test]
+    fn iter_mut_after_snapshot_visits_each_value_once() {
+        let mut trie = RadixTrie::<_>::new();
+        for key in keys(1000) {
+            trie.insert(key, 0);
+        }
+        let snapshot = trie.snapshot();
+        let mut iter = trie.iter_mut();
+        let mut values = Vec::new();
+        while let Some((_, value)) = iter.next() {
+            values.push(value);
+            let Some((_, value)) = iter.next_back() else {
+                break;
+            };
+            values.push(value);
+        }
+        for value in values {
+            *value += 1;
+        }
+        assert!(trie.values().all(|&value| value == 1));
+        assert!(snapshot.values().all(|&value| value == 0));
+        assert_eq!(trie.len(), snapshot.len());
+    }
+
+    #[test]
+    fn snapshots_are_read_on_other_threads() {
+        let mut trie = RadixTrie::<_>::new();
+        for (value, key) in (0..).zip(keys(1000)) {
+            trie.insert(key, value);
+        }
+        let snapshot = trie.snapshot();
+        let before = entries(&snapshot);
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let prefix: Vec<_> = snapshot.iter_prefix(b"key/2/").collect();
+                assert_eq!(
+                    prefix.len(),
+                    before
+                        .keys()
+                        .filter(|key| key.starts_with(b"key/2/"))
+                        .count()
+                );
+                entries(&snapshot)
+            });
+            for key in keys(1000) {
+                trie.remove(key);
+            }
+            assert_eq!(reader.join().unwrap(), before);
+        });
+        assert!(trie.is_empty());
+    }
+
+    /// a value which counts how many times it has been cloned.
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Self(Rc::clone(&self.0))
+        }
+    }
+
+    #[test]
+    fn dropped_snapshots_give_their_chunks_back() {
+        let clones = Rc::new(Cell::new(0));
+        let mut trie = RadixTrie::<_>::new();
+        for key in keys(1000) {
+            trie.insert(key, Counted(Rc::clone(&clones)));
+        }
+
+        // While a snapshot holds the chunks, writes copy them.
+        let snapshot = trie.snapshot();
+        assert_eq!(clones.get(), 0);
+        trie.insert(b"key/0/0".to_vec(), Counted(Rc::clone(&clones)));
+        let copied = clones.get();
+        assert!(
+            copied > 0 && copied < 1000,
+            "{copied} values copied for one insert"
+        );
+        drop(snapshot);
+
+        // Once it is gone, they are taken back without copying.
+        for _ in trie.values_mut() {}
+        trie.insert(b"key/1/1".to_vec(), Counted(Rc::clone(&clones)));
+        trie.remove(b"key/2/2");
+        assert_eq!(clones.get(), copied);
+
+        // A snapshot dropped before any write costs no copies at all.
+        drop(trie.snapshot());
+        for _ in trie.iter_mut() {}
+        assert_eq!(clones.get(), copied);
+        drop(trie.snapshot());
+        trie.shrink_to_fit();
+        assert_eq!(clones.get(), copied);
+    }
+}
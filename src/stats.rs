@@ -0,0 +1,163 @@
+use crate::arena::{Arena, NodeId, ROOT};
+use crate::children::Children;
+use crate::digits::Digits;
+use crate::node::BRANCH_FACTOR;
+
+/// A summary of the shape and memory use of a trie,
+/// as returned by RadixTrie::stats.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// the number of keys stored.
+    pub keys: usize,
+    /// the number of nodes in use, including the root.
+    pub nodes: usize,
+    /// the number of nodes whose children are held in a Node4.
+    pub node4: usize,
+    /// the number of nodes whose children are held in a Node16.
+    pub node16: usize,
+    /// the number of nodes whose children are held in a Node48.
+    pub node48: usize,
+    /// the number of nodes whose children are held in a Node256.
+    pub node256: usize,
+    /// the number of nodes whose children are held in a bitmap,
+    /// which is every node with children under Layout::Bitmap.
+    pub bitmap: usize,
+    /// the most nodes below the root on the path to any key.
+    pub max_depth: usize,
+    /// the sum over all keys of the nodes below the root on their path.
+    pub total_depth: usize,
+    /// `fanout[n]` is the number of nodes with n children.
+    pub fanout: Vec<usize>,
+    /// the sum of the lengths of all keys, in bytes.
+    pub key_bytes: usize,
+    /// an estimate of the memory the trie has allocated on the heap,
+    /// in bytes. Memory owned by the values themselves is not included.
+    pub heap_bytes: usize,
+}
+
+impl Stats {
+    /// walks every node of the arena, deepest last.
+    pub(crate) fn new<T>(arena: &Arena<T>, digits: Digits) -> Self {
+        let mut stats = Stats {
+            fanout: vec![0; digits.radix() + 1],
+            heap_bytes: arena.heap_bytes(),
+            ..Stats::default()
+        };
+
+        // Each node is visited with its depth and the number
+        // of digits in its key.
+        let mut stack: Vec<(NodeId, usize, usize)> = vec![(ROOT, 0, 0)];
+        while let Some((id, depth, length)) = stack.pop() {
+            let node = &arena[id];
+            stats.nodes += 1;
+            stats.heap_bytes += node.label.capacity();
+            if node.accept_state.is_some() {
+                stats.keys += 1;
+                stats.max_depth = stats.max_depth.max(depth);
+                stats.total_depth += depth;
+                stats.key_bytes += digits.bytes(length);
+            }
+
+            let Some(children) = &node.children else {
+                stats.fanout[0] += 1;
+                continue;
+            };
+            stats.fanout[children.len()] += 1;
+            stats.heap_bytes += children.heap_bytes();
+            match children {
+                Children::Node4(_) => stats.node4 += 1,
+                Children::Node16(_) => stats.node16 += 1,
+                Children::Node48(_) => stats.node48 += 1,
+                Children::Node256(_) => stats.node256 += 1,
+                Children::Bitmap(_) => stats.bitmap += 1,
+            }
+            for (_, child) in children.range(0..BRANCH_FACTOR) {
+                let label = arena[child].label.len();
+                stack.push((child, depth + 1, length + 1 + label));
+            }
+        }
+        stats
+    }
+
+    /// returns the mean number of nodes below the
+    /// root on the path to a key, or 0 if there are none.
+    pub fn average_depth(&self) -> f64 {
+        if self.keys == 0 {
+            return 0.0;
+        }
+        self.total_depth as f64 / self.keys as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Layout, RadixTrie};
+
+    #[test]
+    fn stats_describe_a_known_shape() {
+        let mut trie = RadixTrie::new();
+        for key in ["", "car", "cart", "cat", "dog"] {
+            trie.insert(key, ());
+        }
+        // root ─┬ c "a" ─┬ r ── t
+        //       │        └ t
+        //       └ d "og"
+        let stats = trie.stats();
+        assert_eq!(stats.keys, 5);
+        assert_eq!(stats.nodes, 6);
+        assert_eq!(stats.nodes, trie.node_count());
+        assert_eq!(
+            (stats.node4, stats.node16, stats.node48, stats.node256),
+            (3, 0, 0, 0)
+        );
+        assert_eq!(stats.bitmap, 0);
+        assert_eq!(stats.fanout[..3], [3, 1, 2]);
+        assert!(stats.fanout[3..].iter().all(|&n| n == 0));
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.total_depth, 8);
+        assert_eq!(stats.average_depth(), 8.0 / 5.0);
+        assert_eq!(stats.key_bytes, 13);
+        assert!(stats.heap_bytes > 0);
+    }
+
+    #[test]
+    fn stats_count_each_layout() {
+        for (count, layout) in [
+            (3, [1, 0, 0, 0]),
+            (10, [0, 1, 0, 0]),
+            (40, [0, 0, 1, 0]),
+            (200, [0, 0, 0, 1]),
+        ] {
+            let mut trie = RadixTrie::new();
+            for byte in 0..count {
+                trie.insert([byte as u8], ());
+            }
+            let stats = trie.stats();
+            assert_eq!(
+                [stats.node4, stats.node16, stats.node48, stats.node256],
+                layout
+            );
+            assert_eq!(stats.nodes, count + 1);
+            assert_eq!(stats.fanout[count], 1);
+            assert_eq!(stats.fanout[0], count);
+            assert_eq!(stats.max_depth, 1);
+            assert_eq!(stats.key_bytes, count);
+        }
+
+        let mut trie = RadixTrie::<_>::with_layout(Layout::Bitmap);
+        for key in ["", "car", "cart", "cat", "dog"] {
+            trie.insert(key, ());
+        }
+        let stats = trie.stats();
+        assert_eq!(stats.bitmap, 3);
+        assert_eq!(stats.node4 + stats.node16 + stats.node48 + stats.node256, 0);
+    }
+
+    #[test]
+    fn stats_of_an_empty_trie() {
+        let stats = RadixTrie::<u32>::new().stats();
+        assert_eq!((stats.keys, stats.nodes, stats.max_depth), (0, 1, 0));
+        assert_eq!(stats.fanout[0], 1);
+        assert_eq!(stats.average_depth(), 0.0);
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::{
+    BorrowedKey, IntoIter, Iter, IterMut, Layout, Prefix, RadixTrie, Range, TrieKey, Values,
+    ValuesMut,
+};
+
+/// A map from keys of type K to values of type T, stored in a RadixTrie
+/// under the encoding of each key given by TrieKey. Entries are visited
+/// in the order of their keys, so numbers sort numerically rather than
+/// by the bytes of their text, and keys are decoded again on the way out.
+pub struct TypedRadixTrie<K, T> {
+    inner: RadixTrie<T>,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K: TrieKey, T> TypedRadixTrie<K, T> {
+    pub fn new() -> Self {
+        Self::with_layout(Layout::default())
+    }
+
+    /// returns an empty trie whose nodes store their children in layout.
+    pub fn with_layout(layout: Layout) -> Self {
+        Self {
+            inner: RadixTrie::with_layout(layout),
+            marker: PhantomData,
+        }
+    }
+
+    /// returns the number of distinct keys stored in the trie.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// returns a reference to the value stored under key, if any.
+    /// The key may be given in a borrowed form, such as a str for a
+    /// String, to save building a K.
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        self.inner.get(encode(key))
+    }
+
+    /// returns a mutable reference to the value stored under key, if any.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut T>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        self.inner.get_mut(encode(key))
+    }
+
+    /// returns true if a value is stored under exactly this key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        self.inner.contains_key(encode(key))
+    }
+
+    /// stores value under key. If the key was already present,
+    /// the previous value is returned and the length is unchanged.
+    pub fn insert(&mut self, key: K, value: T) -> Option<T> {
+        self.inner.insert(key.to_bytes(), value)
+    }
+
+    /// removes the value stored under key and returns it.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        self.inner.remove(encode(key))
+    }
+
+    /// returns an iterator over the entries of the trie in key order.
+    pub fn iter(&self) -> Decoded<Iter<'_, T>, K> {
+        Decoded::new(self.inner.iter())
+    }
+
+    /// returns an iterator over the entries of the trie with
+    /// mutable references to the values, in key order.
+    pub fn iter_mut(&mut self) -> Decoded<IterMut<'_, T>, K> {
+        Decoded::new(self.inner.iter_mut())
+    }
+
+    /// returns an iterator over the values of the trie in key order.
+    pub fn values(&self) -> Values<'_, T> {
+        self.inner.values()
+    }
+
+    /// returns an iterator over mutable references to the
+    /// values of the trie in key order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, T> {
+        self.inner.values_mut()
+    }
+
+    /// returns an iterator over the entries whose keys begin with
+    /// prefix, such as the tuples whose leading fields are prefix.
+    /// A key only begins with another of the same type if they are
+    /// equal, as encodings are self-delimiting.
+    pub fn iter_prefix<P: TrieKey>(&self, prefix: &P) -> Decoded<Prefix<'_, T>, K> {
+        Decoded::new(self.inner.iter_prefix(prefix.to_bytes()))
+    }
+
+    /// returns an iterator over the entries whose keys fall
+    /// within range, in key order.
+    ///
+    /// Panics if the start of the range is greater than its end, or if
+    /// both are equal and excluded.
+    pub fn range<R>(&self, range: R) -> Decoded<Range<'_, T>, K>
+    where
+        R: RangeBounds<K>,
+    {
+        let start = range.start_bound().map(TrieKey::to_bytes);
+        let end = range.end_bound().map(TrieKey::to_bytes);
+        let bounds = (
+            start.as_ref().map(Vec::as_slice),
+            end.as_ref().map(Vec::as_slice),
+        );
+        Decoded::new(self.inner.range::<(Bound<&[u8]>, Bound<&[u8]>)>(bounds))
+    }
+
+    /// returns the entry with the smallest key.
+    pub fn first_key_value(&self) -> Option<(K, &T)> {
+        self.iter().next()
+    }
+
+    /// returns the entry with the largest key.
+    pub fn last_key_value(&self) -> Option<(K, &T)> {
+        self.iter().next_back()
+    }
+
+    /// removes every key from the trie.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// returns the trie of encoded keys underneath.
+    pub fn into_inner(self) -> RadixTrie<T> {
+        self.inner
+    }
+}
+
+impl<K: TrieKey, T> Default for TypedRadixTrie<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, T: Clone> Clone for TypedRadixTrie<K, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K: TrieKey + fmt::Debug, T: fmt::Debug> fmt::Debug for TypedRadixTrie<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: TrieKey, T> FromIterator<(K, T)> for TypedRadixTrie<K, T> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K: TrieKey, T> Extend<(K, T)> for TypedRadixTrie<K, T> {
+    fn extend<I: IntoIterator<Item = (K, T)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: TrieKey, T> IntoIterator for TypedRadixTrie<K, T> {
+    type Item = (K, T);
+    type IntoIter = Decoded<IntoIter<T>, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Decoded::new(self.inner.into_iter())
+    }
+}
+
+impl<'a, K: TrieKey, T> IntoIterator for &'a TypedRadixTrie<K, T> {
+    type Item = (K, &'a T);
+    type IntoIter = Decoded<Iter<'a, T>, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K: TrieKey, T> IntoIterator for &'a mut TypedRadixTrie<K, T> {
+    type Item = (K, &'a mut T);
+    type IntoIter = Decoded<IterMut<'a, T>, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the entries of a TypedRadixTrie, which
+/// decodes the key of each entry of the trie beneath it.
+pub struct Decoded<I, K> {
+    inner: I,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<I, K> Decoded<I, K> {
+    fn new(inner: I) -> Self {
+        Self {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// encodes a key, or a borrowed form of one, as stored in a TypedRadixTrie.
+fn encode<K: TrieKey, Q: BorrowedKey<K> + ?Sized>(key: &Q) -> Vec<u8> {
+    let mut out = Vec::new();
+    key.encode_key(&mut out);
+    out
+}
+
+/// decodes a key stored in a TypedRadixTrie, which
+/// was encoded from a K when it was inserted.
+fn decode<K: TrieKey>(bytes: Vec<u8>) -> K {
+    let mut input = bytes.as_slice();
+    let key = K::decode(&mut input).expect("keys are encoded from the key type");
+    debug_assert!(input.is_empty(), "keys are encoded from the key type");
+    key
+}
+
+impl<I, K, V> Iterator for Decoded<I, K>
+where
+    I: Iterator<Item = (Vec<u8>, V)>,
+    K: TrieKey,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        Some((decode(key), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<I, K, V> DoubleEndedIterator for Decoded<I, K>
+where
+    I: DoubleEndedIterator<Item = (Vec<u8>, V)>,
+    K: TrieKey,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next_back()?;
+        Some((decode(key), value))
+    }
+}
+
+impl<I, K, V> ExactSizeIterator for Decoded<I, K>
+where
+    I: ExactSizeIterator<Item = (Vec<u8>, V)>,
+    K: TrieKey,
+{
+}
+
+impl<I, K, V> FusedIterator for Decoded<I, K>
+where
+    I: FusedIterator<Item = (Vec<u8>, V)>,
+    K: TrieKey,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TypedRadixTrie;
+
+    #[test]
+    fn lookups_take_borrowed_keys() {
+        let mut trie = TypedRadixTrie::new();
+        trie.insert("apple".to_string(), 1);
+        trie.insert("app\0le".to_string(), 2);
+        assert_eq!(trie.get("apple"), Some(&1));
+        assert_eq!(trie.get(&"apple".to_string()), Some(&1));
+        assert!(trie.contains_key("app\0le"));
+        assert!(!trie.contains_key("app"));
+        *trie.get_mut("apple").unwrap() += 10;
+        assert_eq!(trie.remove("apple"), Some(11));
+        assert_eq!(trie.remove("apple"), None);
+        assert_eq!(trie.len(), 1);
+
+        let mut trie = TypedRadixTrie::new();
+        trie.insert(b"ab\0".to_vec(), 1);
+        assert_eq!(trie.get(&b"ab\0"[..]), Some(&1));
+        assert_eq!(trie.get(b"ab".as_slice()), None);
+
+        // Arrays are stored raw, and are only looked up as arrays.
+        let mut trie = TypedRadixTrie::new();
+        trie.insert(*b"ab", 1);
+        assert_eq!(trie.get(b"ab"), Some(&1));
+    }
+
+    #[test]
+    fn lookups_take_owned_keys() {
+        let mut trie = TypedRadixTrie::new();
+        trie.insert((7u32, -1i64), "a");
+        trie.insert((7u32, 2i64), "b");
+        assert_eq!(trie.get(&(7, -1)), Some(&"a"));
+        assert_eq!(trie.remove(&(7, 2)), Some("b"));
+        let keys: Vec<_> = trie.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, [(7, -1)]);
+    }
+}